@@ -0,0 +1,218 @@
+//! Messages between the game and the lobby server
+//!
+//! Everything goes to the same URL as JSON: `POST` a `JoinGame` to get a `JoinResponse`, `GET`
+//! with a `GetUpdate` and `PUT` a `Putt` to get the lobby's `PublicStates`.
+//!
+//! Instead of polling with `GetUpdate`, a client can open a WebSocket to the same URL with
+//! `?lobby=<id>`. The server sends the lobby's `PublicStates` as a JSON text message right away
+//! and again whenever it changes. Each message only carries the putts not sent on that
+//! socket before.
+//!
+//! A refused `Putt` is answered with a 4xx status and a `PuttError` as the body.
+//!
+//! Private lobbies are opened with `JoinGame::private` and joined with the code in the
+//! `JoinResponse`, never by random players. Their host runs them by `PATCH`ing `HostCommand`s.
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::time::Duration;
+
+/// Largest allowed magnitude of `Putt::impulse`
+pub const MAX_PUTT_IMPULSE: f64 = 800.;
+/// Putts each player gets in a game
+pub const PUTTS_PER_PLAYER: usize = 4;
+/// Length of the play phase, after which the balls are left where they are
+pub const PLAY_TIME: Duration = Duration::from_secs(30);
+/// How far a putt's time can be from the server's game time, to allow for the trip there
+pub const PUTT_TIME_TOLERANCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Player {
+    pub uuid: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JoinGame {
+    pub id: Option<usize>,
+    pub player: Player,
+    /// Join code of a private lobby, used instead of `id`
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Opens a new private lobby with the player as its host
+    #[serde(default)]
+    pub private: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Putt {
+    pub id: usize,
+    pub player: Player,
+    /// Ball the client thinks is theirs, checked against the one the server gave them
+    #[serde(default)]
+    pub ball: Option<usize>,
+    pub time: Duration,
+    pub impulse: [f64; 2],
+}
+
+impl Putt {
+    /// Is the impulse within `MAX_PUTT_IMPULSE`, putts failing this should be rejected
+    pub fn impulse_within_limit(&self) -> bool {
+        let [x, y] = self.impulse;
+
+        x.is_finite() && y.is_finite() && (x * x + y * y).sqrt() <= MAX_PUTT_IMPULSE + 1e-6
+    }
+}
+
+/// Why the server refused a putt
+///
+/// The server doesn't simulate the game, so rules that depend on where the balls are, like
+/// only putting balls at rest, can't be checked here and aren't enforced.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum PuttError {
+    /// The lobby isn't in its play phase
+    NotPlaying,
+    /// The uuid hasn't joined the lobby
+    NotAPlayer,
+    /// The putt was for a ball that belongs to someone else
+    NotYourBall { yours: usize },
+    /// The impulse is over `MAX_PUTT_IMPULSE` or not a number
+    ImpulseOverLimit,
+    /// The player has used all of `PUTTS_PER_PLAYER`
+    NoPuttsLeft,
+    /// The putt is after `PLAY_TIME`
+    AfterGame,
+    /// The putt is further behind the server's game time than `PUTT_TIME_TOLERANCE`
+    TooLate { behind: Duration },
+    /// The putt is further ahead of the server's game time than `PUTT_TIME_TOLERANCE`
+    TooEarly { ahead: Duration },
+}
+
+impl fmt::Display for PuttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PuttError::NotPlaying => write!(f, "the game is not on"),
+            PuttError::NotAPlayer => write!(f, "not a player in this lobby"),
+            PuttError::NotYourBall { yours } => write!(f, "not your ball, yours is {}", yours),
+            PuttError::ImpulseOverLimit => write!(f, "impulse is over the limit"),
+            PuttError::NoPuttsLeft => {
+                write!(f, "no putts left, everyone gets {}", PUTTS_PER_PLAYER)
+            }
+            PuttError::AfterGame => write!(f, "putt is after the end of the game"),
+            PuttError::TooLate { behind } => {
+                write!(f, "putt is {:.2}s behind the game", behind.as_secs_f64())
+            }
+            PuttError::TooEarly { ahead } => {
+                write!(f, "putt is {:.2}s ahead of the game", ahead.as_secs_f64())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GetUpdate {
+    pub id: usize,
+    /// Putts the client already has, only putts with an id at least this are sent back
+    #[serde(default)]
+    pub since: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JoinResponse {
+    pub id: usize,
+    pub uid: usize,
+    pub game: PublicStates,
+    /// Join code of the lobby if it's private
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Is the player the host of the private lobby
+    #[serde(default)]
+    pub host: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostCommand {
+    pub id: usize,
+    /// Has to be the host of the lobby
+    pub player: Player,
+    pub action: HostAction,
+}
+
+/// What the host of a private lobby can do before the game starts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum HostAction {
+    /// Starts the game without waiting for more players
+    Start,
+    /// Removes the player with this id, they can't join the lobby again
+    Kick(usize),
+    /// Plays the course with this file name in the clients' course directory. The server
+    /// doesn't read courses, so the host also tells how many balls it has, one per player.
+    Course { name: String, balls: usize },
+}
+
+/// Is `name` a plain file name that can be looked up in the course directory, not a path that
+/// leads out of it. Course names from the host are checked with this on the server and again
+/// by the clients before loading them.
+pub fn is_course_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && !name.contains(&['/', '\\', ':', '\0'][..])
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicPut {
+    /// Sequence number of the putt in its lobby, starting from 0
+    pub id: usize,
+    pub player: PublicPlayer,
+    pub time: Duration,
+    pub impulse: [f64; 2],
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum PublicStates {
+    WaitingForPlayers(PublicWaitingForPlayers),
+    Warmup(PublicWarmup),
+    Play(PublicPlay),
+    GameOver(PublicGameOver),
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicPlayer {
+    pub id: usize,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicWaitingForPlayers {
+    pub players: Vec<PublicPlayer>,
+    /// File name of the course chosen by the host, the arena when there is none
+    #[serde(default)]
+    pub course: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicWarmup {
+    pub time: Duration,
+    pub players: Vec<PublicPlayer>,
+    /// Same as `PublicWaitingForPlayers::course`
+    #[serde(default)]
+    pub course: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicPlay {
+    pub time: Duration,
+    pub players: Vec<PublicPlayer>,
+    /// Putts after the requested cursor, in the order the server took them
+    pub puts: Vec<PublicPut>,
+    /// Same as `PublicWaitingForPlayers::course`
+    #[serde(default)]
+    pub course: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PublicGameOver {
+    pub time: Duration,
+    pub reserved: Vec<PublicPlayer>,
+    pub discard: Vec<PublicPlayer>,
+}
@@ -0,0 +1,133 @@
+//! Estimate of the server's game time
+//!
+//! The server reports how long the play phase has been on. Each report arrives some time after
+//! it was made, so it only bounds when the phase started: no later than the report's arrival
+//! minus the reported time. When the request's round trip is known, the start is taken to be
+//! half the round trip before that, and the sample with the shortest round trip is trusted
+//! the most.
+
+use std::time::{Duration, Instant};
+
+/// When a request was sent and its answer arrived
+#[derive(Clone, Copy, Debug)]
+pub struct RoundTrip {
+    pub sent: Instant,
+    pub received: Instant,
+}
+
+impl RoundTrip {
+    pub fn duration(&self) -> Duration {
+        self.received.saturating_duration_since(self.sent)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GameClock {
+    /// Latest the game can have started, from every sample so far
+    latest_start: Option<Instant>,
+    /// Start estimated from the sample with the shortest round trip, and that round trip
+    estimate: Option<(Instant, Duration)>,
+}
+
+impl GameClock {
+    pub fn new() -> GameClock {
+        Default::default()
+    }
+
+    /// Takes in a report of `elapsed` game time that arrived at `received`. `round_trip` is
+    /// the request the report answered, pushed reports don't have one.
+    pub fn sample(&mut self, elapsed: Duration, received: Instant, round_trip: Option<RoundTrip>) {
+        let bound = match received.checked_sub(elapsed) {
+            Some(bound) => bound,
+            None => return,
+        };
+
+        self.latest_start = Some(self.latest_start.map_or(bound, |latest| latest.min(bound)));
+
+        if let Some(round_trip) = round_trip {
+            let rtt = round_trip.duration();
+            let better = self.estimate.map_or(true, |(_, best)| rtt < best);
+
+            if better {
+                if let Some(start) = bound.checked_sub(rtt / 2) {
+                    self.estimate = Some((start, rtt));
+                }
+            }
+        }
+    }
+
+    /// Local instant the game started at, none before the first sample
+    pub fn start(&self) -> Option<Instant> {
+        let latest = self.latest_start?;
+
+        // a pushed report can arrive faster than half the best round trip
+        Some(self.estimate.map_or(latest, |(start, _)| start.min(latest)))
+    }
+
+    /// Game time at `now`, zero before the game has started
+    pub fn time(&self, now: Instant) -> Duration {
+        self.start().map_or(Duration::new(0, 0), |start| {
+            now.saturating_duration_since(start)
+        })
+    }
+
+    /// Round trip of the best sample, for the UI
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.estimate.map(|(_, rtt)| rtt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn shortest_round_trip_wins() {
+        let start = Instant::now();
+        let mut clock = GameClock::new();
+        assert_eq!(clock.start(), None);
+        assert_eq!(clock.time(start + ms(500)), ms(0));
+
+        let round_trip = |sent, received| {
+            Some(RoundTrip {
+                sent: start + ms(sent),
+                received: start + ms(received),
+            })
+        };
+
+        // answered halfway through a 400 ms round trip
+        clock.sample(ms(1200), start + ms(1400), round_trip(1000, 1400));
+        // a slower one, answered late in its round trip, doesn't replace it
+        clock.sample(ms(2900), start + ms(3000), round_trip(2000, 3000));
+
+        assert_eq!(clock.start(), Some(start));
+        assert_eq!(clock.round_trip(), Some(ms(400)));
+        assert_eq!(clock.time(start + ms(3500)), ms(3500));
+    }
+
+    #[test]
+    fn pushed_sample_bounds_estimate() {
+        let start = Instant::now();
+        let mut clock = GameClock::new();
+
+        // answered 100 ms before the middle of the round trip, so the estimate is that late
+        clock.sample(
+            ms(1100),
+            start + ms(1400),
+            Some(RoundTrip {
+                sent: start + ms(1000),
+                received: start + ms(1400),
+            }),
+        );
+        assert_eq!(clock.start(), Some(start + ms(100)));
+
+        // a push that arrived right away shows the game started earlier than that
+        clock.sample(ms(2000), start + ms(2000), None);
+        assert_eq!(clock.start(), Some(start));
+        assert_eq!(clock.round_trip(), Some(ms(400)));
+    }
+}
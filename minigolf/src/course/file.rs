@@ -0,0 +1,723 @@
+//! Course file format
+//!
+//! Courses are stored as JSON, see `courses/default.json` for an example.
+
+use crate::course::rack::Formation;
+use crate::course::{Course, Gate, Hole, Polyline, RackBall};
+use crate::physics::{GateAction, Motion, Surface, Teleporter, Trigger, Wall, Zone};
+use mela::nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Version written to new course files, and the only one we can read
+pub const COURSE_FILE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum CourseError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Version {
+        path: PathBuf,
+        found: u32,
+    },
+    Invalid {
+        path: PathBuf,
+        hole: Option<usize>,
+        message: String,
+    },
+}
+
+impl fmt::Display for CourseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CourseError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CourseError::Syntax {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            CourseError::Version { path, found } => write!(
+                f,
+                "{}: unsupported course file version {}, expected {}",
+                path.display(),
+                found,
+                COURSE_FILE_VERSION
+            ),
+            CourseError::Invalid {
+                path,
+                hole: Some(hole),
+                message,
+            } => write!(f, "{}: hole {}: {}", path.display(), hole + 1, message),
+            CourseError::Invalid {
+                path,
+                hole: None,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for CourseError {}
+
+impl CourseError {
+    pub(super) fn syntax(path: &Path, error: serde_json::Error) -> CourseError {
+        // serde_json appends the position to the message, we show it in front instead
+        let location = format!(" at line {} column {}", error.line(), error.column());
+        let message = error.to_string();
+
+        CourseError::Syntax {
+            path: path.to_owned(),
+            line: error.line(),
+            column: error.column(),
+            message: message.trim_end_matches(&location).to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CourseFile {
+    pub version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub holes: Vec<HoleFile>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoleFile {
+    pub name: String,
+    pub par: u32,
+    /// Can be left out if a rack ball is the player's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tee: Option<[f64; 2]>,
+    pub cup: [f64; 2],
+    #[serde(default = "default_cup_radius")]
+    pub cup_radius: f64,
+    #[serde(default = "default_ball_radius")]
+    pub ball_radius: f64,
+    #[serde(default)]
+    pub walls: Vec<WallFile>,
+    #[serde(default)]
+    pub zones: Vec<ZoneFile>,
+    #[serde(default)]
+    pub teleporters: Vec<TeleporterFile>,
+    #[serde(default)]
+    pub gates: Vec<GateFile>,
+    #[serde(default)]
+    pub triggers: Vec<TriggerFile>,
+    #[serde(default)]
+    pub rack: Vec<RackFile>,
+}
+
+/// A ball or a formation of balls
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RackFile {
+    /// Ball in the hole's ball radius
+    Position([f64; 2]),
+    Ball(BallFile),
+    Formation(FormationFile),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BallFile {
+    pub position: [f64; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>,
+    /// Is this where the player ball starts, instead of the tee. The player ball always looks
+    /// the same, the style is ignored for it.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub player: bool,
+}
+
+/// Unset fields come from the hole
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BallStyleFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FormationFile {
+    #[serde(flatten)]
+    pub formation: Formation,
+    /// Style of every ball in the formation
+    #[serde(default)]
+    pub ball: BallStyleFile,
+    /// Index of the ball in the formation where the player ball starts, instead of the tee
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<usize>,
+}
+
+/// Polyline of walls
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WallFile {
+    pub points: Vec<[f64; 2]>,
+    #[serde(default)]
+    pub closed: bool,
+    /// Makes this a moving wall, `points` being where it is when the hole starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<MotionFile>,
+    /// Balls only bounce off the left side, looking from each point to the next
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub one_way: bool,
+}
+
+impl From<&WallFile> for Polyline {
+    fn from(wall: &WallFile) -> Polyline {
+        Polyline {
+            points: wall.points.iter().map(point).collect(),
+            closed: wall.closed,
+            motion: wall.motion.map(Motion::from),
+            one_way: wall.one_way,
+        }
+    }
+}
+
+impl From<&Polyline> for WallFile {
+    fn from(wall: &Polyline) -> WallFile {
+        WallFile {
+            points: wall.points.iter().map(array).collect(),
+            closed: wall.closed,
+            motion: wall.motion.map(MotionFile::from),
+            one_way: wall.one_way,
+        }
+    }
+}
+
+/// Walls that triggers open and close
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GateFile {
+    pub walls: Vec<WallFile>,
+    /// Is the gate open when the hole starts
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub open: bool,
+}
+
+/// Line that opens or closes a gate when a ball crosses it
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerFile {
+    pub start: [f64; 2],
+    pub end: [f64; 2],
+    /// Index of the gate in the hole's gates, counting from 0
+    pub gate: usize,
+    #[serde(default)]
+    pub action: GateActionFile,
+    /// Makes this a switch that balls bounce off, instead of a line they roll over
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub switch: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateActionFile {
+    Open,
+    Close,
+    Toggle,
+}
+
+impl Default for GateActionFile {
+    fn default() -> GateActionFile {
+        GateActionFile::Toggle
+    }
+}
+
+impl From<GateActionFile> for GateAction {
+    fn from(action: GateActionFile) -> GateAction {
+        match action {
+            GateActionFile::Open => GateAction::Open,
+            GateActionFile::Close => GateAction::Close,
+            GateActionFile::Toggle => GateAction::Toggle,
+        }
+    }
+}
+
+impl From<GateAction> for GateActionFile {
+    fn from(action: GateAction) -> GateActionFile {
+        match action {
+            GateAction::Open => GateActionFile::Open,
+            GateAction::Close => GateActionFile::Close,
+            GateAction::Toggle => GateActionFile::Toggle,
+        }
+    }
+}
+
+/// Path of a moving wall, repeated for as long as the hole is played
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MotionFile {
+    /// Spins around `pivot`, `speed` in degrees per second clockwise
+    Rotate { pivot: [f64; 2], speed: f64 },
+    /// Slides `offset` away and back once every `period` seconds, starting `phase` periods in
+    Slide {
+        offset: [f64; 2],
+        period: f64,
+        #[serde(default)]
+        phase: f64,
+    },
+}
+
+impl From<MotionFile> for Motion {
+    fn from(motion: MotionFile) -> Motion {
+        match motion {
+            MotionFile::Rotate { pivot, speed } => Motion::Rotate {
+                pivot: point(&pivot),
+                speed: speed.to_radians(),
+            },
+            MotionFile::Slide {
+                offset,
+                period,
+                phase,
+            } => Motion::Slide {
+                offset: Vector2::new(offset[0], offset[1]),
+                period,
+                phase,
+            },
+        }
+    }
+}
+
+impl From<Motion> for MotionFile {
+    fn from(motion: Motion) -> MotionFile {
+        match motion {
+            Motion::Rotate { pivot, speed } => MotionFile::Rotate {
+                pivot: array(&pivot),
+                speed: speed.to_degrees(),
+            },
+            Motion::Slide {
+                offset,
+                period,
+                phase,
+            } => MotionFile::Slide {
+                offset: [offset.x, offset.y],
+                period,
+                phase,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneFile {
+    pub polygon: Vec<[f64; 2]>,
+    #[serde(default = "default_friction")]
+    pub friction: f64,
+    #[serde(default = "default_restitution")]
+    pub restitution: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TeleporterFile {
+    pub entry: [f64; 2],
+    pub exit: [f64; 2],
+    /// Radius of the entry pad
+    #[serde(default = "default_pad_radius")]
+    pub radius: f64,
+    /// Direction balls leave the exit in, in degrees clockwise from the right. They keep going
+    /// the way they were going if left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<f64>,
+    /// Multiplier for the speed of teleported balls
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+pub(super) fn default_cup_radius() -> f64 {
+    14.
+}
+
+pub(super) fn default_ball_radius() -> f64 {
+    6.1335
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+fn default_pad_radius() -> f64 {
+    12.
+}
+
+fn default_speed() -> f64 {
+    1.
+}
+
+fn default_friction() -> f64 {
+    Surface::default().friction
+}
+
+fn default_restitution() -> f64 {
+    Surface::default().restitution
+}
+
+pub(super) fn read(path: &Path) -> Result<String, CourseError> {
+    fs::read_to_string(path).map_err(|error| CourseError::Io {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+fn point(p: &[f64; 2]) -> Point2<f64> {
+    Point2::new(p[0], p[1])
+}
+
+fn array(p: &Point2<f64>) -> [f64; 2] {
+    [p.x, p.y]
+}
+
+impl CourseFile {
+    pub fn load(path: &Path) -> Result<CourseFile, CourseError> {
+        CourseFile::parse(path, &read(path)?)
+    }
+
+    /// `path` is only used for error messages
+    pub fn parse(path: &Path, source: &str) -> Result<CourseFile, CourseError> {
+        let probe: VersionProbe =
+            serde_json::from_str(source).map_err(|e| CourseError::syntax(path, e))?;
+
+        if probe.version != COURSE_FILE_VERSION {
+            return Err(CourseError::Version {
+                path: path.to_owned(),
+                found: probe.version,
+            });
+        }
+
+        serde_json::from_str(source).map_err(|e| CourseError::syntax(path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CourseError> {
+        let json = serde_json::to_string_pretty(self).expect("course serializes");
+
+        fs::write(path, json).map_err(|error| CourseError::Io {
+            path: path.to_owned(),
+            error,
+        })
+    }
+
+    /// `path` is only used for error messages
+    pub fn into_course(self, path: &Path) -> Result<Course, CourseError> {
+        let invalid = |hole: usize, message: String| CourseError::Invalid {
+            path: path.to_owned(),
+            hole: Some(hole),
+            message,
+        };
+
+        let mut holes = Vec::with_capacity(self.holes.len());
+
+        for (i, hole) in self.holes.into_iter().enumerate() {
+            if hole.par == 0 {
+                return Err(invalid(i, "par must be at least 1".to_owned()));
+            }
+
+            if hole.cup_radius <= 0. || hole.ball_radius <= 0. {
+                return Err(invalid(i, "radii must be positive".to_owned()));
+            }
+
+            if let Some(w) = hole.walls.iter().position(|w| w.points.len() < 2) {
+                return Err(invalid(i, format!("wall {} has less than 2 points", w + 1)));
+            }
+
+            let still = hole.walls.iter().position(|w| match w.motion {
+                Some(MotionFile::Rotate { speed, .. }) => speed == 0. || !speed.is_finite(),
+                Some(MotionFile::Slide { period, .. }) => period <= 0. || !period.is_finite(),
+                None => false,
+            });
+
+            if let Some(w) = still {
+                return Err(invalid(
+                    i,
+                    format!("wall {} has a motion with no speed or period", w + 1),
+                ));
+            }
+
+            if let Some(z) = hole.zones.iter().position(|z| z.polygon.len() < 3) {
+                return Err(invalid(i, format!("zone {} has less than 3 points", z + 1)));
+            }
+
+            for (t, teleporter) in hole.teleporters.iter().enumerate() {
+                if teleporter.radius <= 0. || teleporter.speed <= 0. {
+                    return Err(invalid(
+                        i,
+                        format!("teleporter {} must have a positive radius and speed", t + 1),
+                    ));
+                }
+            }
+
+            for (g, gate) in hole.gates.iter().enumerate() {
+                if gate
+                    .walls
+                    .iter()
+                    .any(|w| w.points.len() < 2 || w.motion.is_some())
+                {
+                    return Err(invalid(
+                        i,
+                        format!(
+                            "gate {} has a wall with less than 2 points or a motion",
+                            g + 1
+                        ),
+                    ));
+                }
+            }
+
+            if let Some(t) = hole
+                .triggers
+                .iter()
+                .position(|t| t.gate >= hole.gates.len())
+            {
+                return Err(invalid(i, format!("trigger {} has no such gate", t + 1)));
+            }
+
+            let mut rack = Vec::new();
+            let mut tee = hole.tee.as_ref().map(point);
+
+            let start_player = |tee: &mut Option<Point2<f64>>, p: Point2<f64>| {
+                if tee.replace(p).is_some() {
+                    Err(invalid(i, "more than one tee or player ball".to_owned()))
+                } else {
+                    Ok(())
+                }
+            };
+
+            for entry in &hole.rack {
+                let (positions, style, player) = match entry {
+                    RackFile::Position(p) => (vec![point(p)], BallStyleFile::default(), None),
+                    RackFile::Ball(ball) => (
+                        vec![point(&ball.position)],
+                        BallStyleFile {
+                            radius: ball.radius,
+                            mass: ball.mass,
+                            color: ball.color,
+                        },
+                        Some(0).filter(|_| ball.player),
+                    ),
+                    RackFile::Formation(formation) => (
+                        formation
+                            .formation
+                            .positions(formation.ball.radius.unwrap_or(hole.ball_radius))
+                            .map_err(|e| invalid(i, e))?,
+                        formation.ball.clone(),
+                        formation.player,
+                    ),
+                };
+
+                if player.map_or(false, |p| p >= positions.len()) {
+                    return Err(invalid(i, "player ball is not in the formation".to_owned()));
+                }
+
+                for (j, position) in positions.into_iter().enumerate() {
+                    if player == Some(j) {
+                        start_player(&mut tee, position)?;
+                        continue;
+                    }
+
+                    rack.push(RackBall {
+                        position,
+                        radius: style.radius.unwrap_or(hole.ball_radius),
+                        mass: style.mass.unwrap_or(1.),
+                        color: style.color,
+                    });
+                }
+            }
+
+            if rack.iter().any(|b| b.radius <= 0. || b.mass <= 0.) {
+                return Err(invalid(
+                    i,
+                    "ball radii and masses must be positive".to_owned(),
+                ));
+            }
+
+            let tee = tee.ok_or_else(|| invalid(i, "hole has no tee".to_owned()))?;
+
+            holes.push(Hole {
+                name: hole.name,
+                par: hole.par,
+                tee,
+                cup: point(&hole.cup),
+                cup_radius: hole.cup_radius,
+                walls: hole.walls.iter().map(Polyline::from).collect(),
+                zones: hole
+                    .zones
+                    .iter()
+                    .map(|z| Zone {
+                        polygon: z.polygon.iter().map(point).collect(),
+                        surface: Surface {
+                            friction: z.friction,
+                            restitution: z.restitution,
+                        },
+                    })
+                    .collect(),
+                teleporters: hole
+                    .teleporters
+                    .iter()
+                    .map(|t| Teleporter {
+                        entry: point(&t.entry),
+                        radius: t.radius,
+                        exit: point(&t.exit),
+                        direction: t.direction.map(f64::to_radians),
+                        speed: t.speed,
+                    })
+                    .collect(),
+                gates: hole
+                    .gates
+                    .iter()
+                    .map(|g| Gate {
+                        walls: g.walls.iter().map(Polyline::from).collect(),
+                        open: g.open,
+                    })
+                    .collect(),
+                triggers: hole
+                    .triggers
+                    .iter()
+                    .map(|t| Trigger {
+                        line: Wall {
+                            start: point(&t.start),
+                            end: point(&t.end),
+                            one_way: false,
+                        },
+                        switch: t.switch,
+                        gate: t.gate,
+                        action: t.action.into(),
+                    })
+                    .collect(),
+                rack,
+                ball_radius: hole.ball_radius,
+            });
+        }
+
+        if holes.is_empty() {
+            return Err(CourseError::Invalid {
+                path: path.to_owned(),
+                hole: None,
+                message: "course has no holes".to_owned(),
+            });
+        }
+
+        Ok(Course {
+            name: self.name,
+            author: self.author,
+            holes,
+        })
+    }
+}
+
+impl From<&Course> for CourseFile {
+    fn from(course: &Course) -> CourseFile {
+        CourseFile {
+            version: COURSE_FILE_VERSION,
+            name: course.name.clone(),
+            author: course.author.clone(),
+            holes: course.holes.iter().map(HoleFile::from).collect(),
+        }
+    }
+}
+
+impl From<&Hole> for HoleFile {
+    fn from(hole: &Hole) -> HoleFile {
+        HoleFile {
+            name: hole.name.clone(),
+            par: hole.par,
+            tee: Some(array(&hole.tee)),
+            cup: array(&hole.cup),
+            cup_radius: hole.cup_radius,
+            ball_radius: hole.ball_radius,
+            walls: hole.walls.iter().map(WallFile::from).collect(),
+            zones: hole
+                .zones
+                .iter()
+                .map(|z| ZoneFile {
+                    polygon: z.polygon.iter().map(array).collect(),
+                    friction: z.surface.friction,
+                    restitution: z.surface.restitution,
+                })
+                .collect(),
+            teleporters: hole
+                .teleporters
+                .iter()
+                .map(|t| TeleporterFile {
+                    entry: array(&t.entry),
+                    exit: array(&t.exit),
+                    radius: t.radius,
+                    direction: t.direction.map(f64::to_degrees),
+                    speed: t.speed,
+                })
+                .collect(),
+            gates: hole
+                .gates
+                .iter()
+                .map(|g| GateFile {
+                    walls: g.walls.iter().map(WallFile::from).collect(),
+                    open: g.open,
+                })
+                .collect(),
+            triggers: hole
+                .triggers
+                .iter()
+                .map(|t| TriggerFile {
+                    start: array(&t.line.start),
+                    end: array(&t.line.end),
+                    gate: t.gate,
+                    action: t.action.into(),
+                    switch: t.switch,
+                })
+                .collect(),
+            // formations are written out ball by ball, they may have been edited since
+            rack: hole
+                .rack
+                .iter()
+                .map(|b| {
+                    let ball = BallFile {
+                        position: array(&b.position),
+                        radius: Some(b.radius).filter(|r| *r != hole.ball_radius),
+                        mass: Some(b.mass).filter(|m| *m != 1.),
+                        color: b.color,
+                        player: false,
+                    };
+
+                    match ball {
+                        BallFile {
+                            radius: None,
+                            mass: None,
+                            color: None,
+                            ..
+                        } => RackFile::Position(ball.position),
+                        ball => RackFile::Ball(ball),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
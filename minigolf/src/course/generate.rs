@@ -0,0 +1,356 @@
+//! Procedurally generated holes
+//!
+//! A hole is a walled corridor winding from the tee to the cup, with blocks and sand or ice
+//! patches thrown in. Every hole is checked with the validator, and generated again from the
+//! same random sequence until it passes, so a seed always gives the same hole. When no try
+//! passes, the hole is made simpler, down to a straight corridor across the window.
+
+use super::file::{default_ball_radius, default_cup_radius};
+use super::validate::validate_hole;
+use super::{Course, Hole, Polyline};
+use crate::physics::{Surface, Zone};
+use crate::rng::Rng;
+use mela::nalgebra::{Point2, Vector2};
+use std::f64::consts::PI;
+
+/// Holes are generated to fit the window
+const BOUNDS: [f64; 2] = [1280., 720.];
+/// Space left between the corridor and the edges of the window
+const MARGIN: f64 = 40.;
+/// Tries at a hole before it's made simpler
+const MAX_ATTEMPTS: usize = 16;
+/// Holes in a daily challenge, each one harder than the last
+const DAILY_HOLES: usize = 6;
+
+#[derive(Clone, Debug)]
+pub struct Difficulty {
+    /// Turns in the corridor between the tee and the cup
+    pub bends: usize,
+    /// Width of the corridor
+    pub width: f64,
+    /// Walled blocks in the corridor
+    pub obstacles: usize,
+    /// Sand and ice patches
+    pub zones: usize,
+}
+
+impl Difficulty {
+    /// Gets harder with `level`, starting from 0
+    pub fn level(level: usize) -> Difficulty {
+        Difficulty {
+            bends: (1 + level / 2).min(4),
+            width: (140. - 12. * level as f64).max(70.),
+            obstacles: (level / 2).min(4),
+            zones: ((level + 1) / 3).min(3),
+        }
+    }
+
+    /// Just as wide, without bends or anything in the corridor
+    fn straight(&self) -> Difficulty {
+        Difficulty {
+            bends: 0,
+            obstacles: 0,
+            zones: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Center line of the corridor
+struct Path {
+    points: Vec<Point2<f64>>,
+}
+
+impl Path {
+    fn direction(&self, segment: usize) -> Vector2<f64> {
+        (self.points[segment + 1] - self.points[segment]).normalize()
+    }
+
+    fn normal(&self, segment: usize) -> Vector2<f64> {
+        let d = self.direction(segment);
+        Vector2::new(-d.y, d.x)
+    }
+
+    fn length(&self) -> f64 {
+        self.points.windows(2).map(|w| (w[1] - w[0]).norm()).sum()
+    }
+
+    /// Point `distance` along the path, and the segment it is on
+    fn at(&self, mut distance: f64) -> (Point2<f64>, usize) {
+        let last = self.points.len() - 2;
+
+        for segment in 0..=last {
+            let length = (self.points[segment + 1] - self.points[segment]).norm();
+
+            if distance <= length || segment == last {
+                let p = self.points[segment] + self.direction(segment) * distance.min(length);
+                return (p, segment);
+            }
+
+            distance -= length;
+        }
+
+        unreachable!()
+    }
+
+    /// Walls on both sides of the path, mitered at the bends
+    fn corridor(&self, half_width: f64) -> Polyline {
+        let last = self.points.len() - 1;
+
+        let offsets: Vec<Vector2<f64>> = (0..=last)
+            .map(|i| {
+                let n = match i {
+                    0 => self.normal(0),
+                    i if i == last => self.normal(last - 1),
+                    i => {
+                        let miter = (self.normal(i - 1) + self.normal(i)).normalize();
+                        miter / miter.dot(&self.normal(i))
+                    }
+                };
+
+                n * half_width
+            })
+            .collect();
+
+        let left = self.points.iter().zip(&offsets).map(|(p, o)| p + o);
+        let right = self.points.iter().zip(&offsets).map(|(p, o)| p - o);
+
+        Polyline {
+            points: left.chain(right.rev()).collect(),
+            closed: true,
+            motion: None,
+            one_way: false,
+        }
+    }
+}
+
+fn random_path(rng: &mut Rng, difficulty: &Difficulty) -> Path {
+    let half_width = difficulty.width / 2.;
+    let (left, right) = (MARGIN + half_width, BOUNDS[0] - MARGIN - half_width);
+    let (top, bottom) = (MARGIN + half_width, BOUNDS[1] - MARGIN - half_width);
+
+    let count = difficulty.bends + 2;
+    let step = (right - left) / (count - 1) as f64;
+    let mirrored = rng.chance(0.5);
+
+    let mut y = rng.range(top, bottom);
+    let mut points = Vec::with_capacity(count);
+
+    for i in 0..count {
+        // at most 45 degrees up or down, so that the corridor doesn't fold over itself
+        if i > 0 {
+            y = (y + rng.range(-step, step)).max(top).min(bottom);
+        }
+
+        let x = left + step * i as f64;
+        let x = if mirrored { BOUNDS[0] - x } else { x };
+
+        points.push(Point2::new(x, y));
+    }
+
+    Path { points }
+}
+
+/// Regular polygon with a random number of sides and rotation
+fn random_block(rng: &mut Rng, center: Point2<f64>, radius: f64) -> Polyline {
+    let sides = 3 + rng.below(3);
+    let rotation = rng.range(0., 2. * PI);
+
+    Polyline {
+        points: (0..sides)
+            .map(|i| {
+                let angle = rotation + i as f64 / sides as f64 * 2. * PI;
+                center + Vector2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect(),
+        closed: true,
+        motion: None,
+        one_way: false,
+    }
+}
+
+fn random_hole(rng: &mut Rng, name: &str, difficulty: &Difficulty) -> Hole {
+    let path = random_path(rng, difficulty);
+    hole_along(rng, path, name, difficulty)
+}
+
+/// Hole with its corridor along `path`, and random obstacles and zones in it
+fn hole_along(rng: &mut Rng, path: Path, name: &str, difficulty: &Difficulty) -> Hole {
+    let ball_radius = default_ball_radius();
+    let half_width = difficulty.width / 2.;
+    let length = path.length();
+    let last = path.points.len() - 1;
+
+    let tee = path.points[0] + path.direction(0) * half_width;
+    let cup = path.points[last] - path.direction(last - 1) * half_width;
+
+    // keep the tee and the cup clear
+    let clear = |p: &Point2<f64>, size: f64| {
+        (p - tee).norm() > difficulty.width + size && (p - cup).norm() > difficulty.width + size
+    };
+
+    let mut walls = vec![path.corridor(half_width)];
+    let mut blocks: Vec<(Point2<f64>, f64)> = Vec::new();
+
+    for _ in 0..difficulty.obstacles {
+        let radius = rng.range(0.15, 0.3) * difficulty.width;
+        let room = half_width - radius - ball_radius * 4.;
+        let (along, segment) = path.at(rng.range(0.2, 0.8) * length);
+        let center = along + path.normal(segment) * rng.range(-1., 1.) * room.max(0.);
+
+        let overlaps = blocks
+            .iter()
+            .any(|(c, r)| (c - center).norm() < r + radius + ball_radius * 4.);
+
+        if room > 0. && !overlaps && clear(&center, radius) {
+            walls.push(random_block(rng, center, radius));
+            blocks.push((center, radius));
+        }
+    }
+
+    let mut zones = Vec::new();
+
+    for _ in 0..difficulty.zones {
+        let (center, segment) = path.at(rng.range(0.15, 0.85) * length);
+        let along = path.direction(segment) * rng.range(0.25, 0.75) * difficulty.width;
+        let across = path.normal(segment) * half_width;
+
+        let surface = if rng.chance(0.5) {
+            Surface {
+                friction: 3.,
+                restitution: 0.5,
+            }
+        } else {
+            Surface {
+                friction: 0.3,
+                ..Surface::default()
+            }
+        };
+
+        if clear(&center, along.norm()) {
+            zones.push(Zone {
+                polygon: vec![
+                    center - along - across,
+                    center + along - across,
+                    center + along + across,
+                    center - along + across,
+                ],
+                surface,
+            });
+        }
+    }
+
+    let par = 2 + difficulty.bends as u32 / 2 + (blocks.len() + zones.len() > 2) as u32;
+
+    Hole {
+        name: name.to_owned(),
+        par: par.min(5),
+        tee,
+        cup,
+        cup_radius: default_cup_radius(),
+        walls,
+        zones,
+        teleporters: Vec::new(),
+        gates: Vec::new(),
+        triggers: Vec::new(),
+        rack: Vec::new(),
+        ball_radius,
+    }
+}
+
+/// Corridor straight across the middle with the cup at the far end and nothing in the way,
+/// as wide as the corridors of `difficulty`
+fn straight_hole(rng: &mut Rng, name: &str, difficulty: &Difficulty) -> Hole {
+    let straight = difficulty.straight();
+    let middle = BOUNDS[1] / 2.;
+    let half_width = straight.width / 2.;
+    let path = Path {
+        points: vec![
+            Point2::new(MARGIN + half_width, middle),
+            Point2::new(BOUNDS[0] - MARGIN - half_width, middle),
+        ],
+    };
+
+    hole_along(rng, path, name, &straight)
+}
+
+/// A hole that passes the validator, the same one every time for the same seed
+pub fn generate_hole(seed: u64, name: &str, difficulty: &Difficulty) -> Hole {
+    let mut rng = Rng::new(seed);
+
+    for difficulty in &[difficulty.clone(), difficulty.straight()] {
+        for _ in 0..MAX_ATTEMPTS {
+            let hole = random_hole(&mut rng.fork(), name, difficulty);
+
+            if validate_hole(0, &hole).is_empty() {
+                return hole;
+            }
+        }
+    }
+
+    // always passes, see the test below
+    straight_hole(&mut rng, name, difficulty)
+}
+
+/// Course of holes getting harder, starting from `first_level`
+pub fn generate_course(seed: u64, name: &str, holes: usize, first_level: usize) -> Course {
+    let mut rng = Rng::new(seed);
+
+    Course {
+        name: name.to_owned(),
+        author: None,
+        holes: (0..holes)
+            .map(|i| {
+                let name = format!("Hole {}", i + 1);
+                generate_hole(rng.next_u64(), &name, &Difficulty::level(first_level + i))
+            })
+            .collect(),
+    }
+}
+
+/// Year, month and day of a day counted from 1970-01-01
+fn civil_date(day: u64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (m <= 2) as i64;
+
+    (y, m as u32, d as u32)
+}
+
+/// Course of the day, `day` counted from 1970-01-01 in UTC so everyone gets the same one
+pub fn daily_course(day: u64) -> Course {
+    let (year, month, date) = civil_date(day);
+    let name = format!("Daily challenge {}-{:02}-{:02}", year, month, date);
+
+    generate_course(day, &name, DAILY_HOLES, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn straight_hole_passes_validator() {
+        for level in 0..10 {
+            for seed in 0..64 {
+                let hole = straight_hole(&mut Rng::new(seed), "Hole", &Difficulty::level(level));
+                let warnings = validate_hole(0, &hole);
+
+                assert!(
+                    warnings.is_empty(),
+                    "level {}, seed {}: {:?}",
+                    level,
+                    seed,
+                    warnings
+                );
+            }
+        }
+    }
+}
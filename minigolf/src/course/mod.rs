@@ -0,0 +1,174 @@
+//! Holes and courses
+
+mod file;
+mod generate;
+mod rack;
+mod svg;
+mod tiled;
+mod validate;
+
+use crate::physics::{self, Fixtures, KinematicWall, Motion, Teleporter, Trigger, Wall, Zone};
+use mela::nalgebra::Point2;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub use file::{CourseError, CourseFile, COURSE_FILE_VERSION};
+pub use generate::{daily_course, generate_course};
+pub use validate::{
+    check_reachable, lint_hole, needs_reachability, validate, validate_hole, Warning,
+};
+
+#[derive(Clone, Debug)]
+pub struct Hole {
+    pub name: String,
+    pub par: u32,
+    pub tee: Point2<f64>,
+    pub cup: Point2<f64>,
+    pub cup_radius: f64,
+    pub walls: Vec<Polyline>,
+    pub zones: Vec<Zone>,
+    pub teleporters: Vec<Teleporter>,
+    pub gates: Vec<Gate>,
+    pub triggers: Vec<Trigger>,
+    /// Extra balls on the hole
+    pub rack: Vec<RackBall>,
+    pub ball_radius: f64,
+}
+
+impl Hole {
+    /// Static walls as the physics engine sees them
+    pub fn wall_segments(&self) -> Vec<Wall<f64>> {
+        self.walls
+            .iter()
+            .filter(|p| p.motion.is_none())
+            .flat_map(|p| p.segments())
+            .collect()
+    }
+
+    /// Zones, moving walls, teleporters and gates
+    pub fn fixtures(&self) -> Fixtures {
+        let kinematic_walls = self
+            .walls
+            .iter()
+            .filter_map(|p| p.motion.map(|motion| (p, motion)))
+            .flat_map(|(p, motion)| {
+                p.segments()
+                    .into_iter()
+                    .map(move |rest| KinematicWall { rest, motion })
+            })
+            .collect();
+
+        let gates = self
+            .gates
+            .iter()
+            .map(|g| physics::Gate {
+                walls: g.walls.iter().flat_map(|p| p.segments()).collect(),
+                open: g.open,
+            })
+            .collect();
+
+        Fixtures::new(
+            self.zones.clone(),
+            kinematic_walls,
+            self.teleporters.clone(),
+            gates,
+            self.triggers.clone(),
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RackBall {
+    pub position: Point2<f64>,
+    pub radius: f64,
+    pub mass: f64,
+    /// Drawn in the default rack color if not set
+    pub color: Option<[f32; 4]>,
+}
+
+/// Chain of walls
+#[derive(Clone, Debug)]
+pub struct Polyline {
+    pub points: Vec<Point2<f64>>,
+    /// Is the last point connected back to the first one
+    pub closed: bool,
+    /// Moves the whole chain, `points` being where it is when the hole starts
+    pub motion: Option<Motion>,
+    /// Balls only bounce off the left side, looking from each point to the next
+    pub one_way: bool,
+}
+
+impl Polyline {
+    pub fn segments(&self) -> Vec<Wall<f64>> {
+        let mut segments: Vec<Wall<f64>> = self
+            .points
+            .windows(2)
+            .map(|w| Wall {
+                start: w[0].clone(),
+                end: w[1].clone(),
+                one_way: self.one_way,
+            })
+            .collect();
+
+        if self.closed && self.points.len() > 2 {
+            segments.push(Wall {
+                start: self.points[self.points.len() - 1].clone(),
+                end: self.points[0].clone(),
+                one_way: self.one_way,
+            });
+        }
+
+        segments
+    }
+}
+
+/// Walls that triggers open and close
+#[derive(Clone, Debug)]
+pub struct Gate {
+    pub walls: Vec<Polyline>,
+    /// Is the gate open when the hole starts
+    pub open: bool,
+}
+
+/// Ordered list of holes, played one after another
+#[derive(Clone, Debug)]
+pub struct Course {
+    pub name: String,
+    pub author: Option<String>,
+    pub holes: Vec<Hole>,
+}
+
+impl Course {
+    /// Loads our own course files, and Tiled maps and SVG drawings as single hole courses
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Course, CourseError> {
+        let path = path.as_ref();
+        let source = file::read(path)?;
+
+        let file = match path.extension().and_then(|e| e.to_str()) {
+            Some("tmx") => tiled::parse_tmx(path, &source)?,
+            Some("tmj") => tiled::parse_json(path, &source)?,
+            Some("svg") => svg::parse_svg(path, &source)?,
+            _ if tiled::is_tiled_json(&source) => tiled::parse_json(path, &source)?,
+            _ => CourseFile::parse(path, &source)?,
+        };
+
+        file.into_course(path)
+    }
+
+    pub fn par(&self) -> u32 {
+        self.holes.iter().map(|h| h.par).sum()
+    }
+}
+
+/// Where edits to the course loaded from `path` are saved. Our own course files are
+/// overwritten, imported maps and drawings get a course file next to them.
+pub fn save_path(path: &Path) -> PathBuf {
+    let is_course_file = path.extension().map_or(false, |e| e == "json")
+        && fs::read_to_string(path).map_or(true, |source| !tiled::is_tiled_json(&source));
+
+    if is_course_file {
+        path.to_owned()
+    } else {
+        path.with_extension("course.json")
+    }
+}
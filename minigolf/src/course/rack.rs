@@ -0,0 +1,165 @@
+//! Ball formations for racks
+
+use crate::rng::Rng;
+use mela::nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Tries per ball at finding a free spot in a random formation
+const RANDOM_ATTEMPTS: usize = 100;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "formation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Formation {
+    /// Rows of balls starting from `origin`, filled left to right and top to bottom
+    Grid {
+        origin: [f64; 2],
+        columns: usize,
+        rows: usize,
+        /// Leaves the last row partly empty
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        count: Option<usize>,
+        /// Distance between ball centers, three radii by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        spacing: Option<f64>,
+        /// Every other ball is pushed down by this much, or up if negative, so that the balls don't
+        /// line up
+        #[serde(default)]
+        stagger: f64,
+    },
+    /// Billiards rack, one ball at `apex` and a row more for each row away from it
+    Triangle {
+        apex: [f64; 2],
+        rows: usize,
+        /// Direction the rows grow in, in degrees clockwise from the right
+        #[serde(default)]
+        angle: f64,
+        /// Distance between ball centers, a bit over a diameter by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        spacing: Option<f64>,
+    },
+    /// Evenly spaced around a circle, the first ball to the right of the center
+    Circle {
+        center: [f64; 2],
+        radius: f64,
+        count: usize,
+    },
+    /// Scattered in a `size` rectangle around `center` without touching, the same way every
+    /// time for a seed
+    Random {
+        center: [f64; 2],
+        size: [f64; 2],
+        count: usize,
+        seed: u64,
+    },
+}
+
+impl Formation {
+    /// Ball centers for balls of `ball_radius`
+    pub fn positions(&self, ball_radius: f64) -> Result<Vec<Point2<f64>>, String> {
+        match *self {
+            Formation::Grid {
+                origin,
+                columns,
+                rows,
+                count,
+                spacing,
+                stagger,
+            } => {
+                let spacing = spacing.unwrap_or(ball_radius * 3.);
+                let count = count.unwrap_or(columns * rows);
+
+                if count > columns * rows {
+                    return Err(format!(
+                        "{} balls don't fit a {}x{} grid",
+                        count, columns, rows
+                    ));
+                }
+
+                Ok((0..count)
+                    .map(|i| {
+                        let x = origin[0] + (i % columns) as f64 * spacing;
+                        let y = origin[1] + (i / columns) as f64 * spacing;
+                        Point2::new(x, y + (i % 2) as f64 * stagger)
+                    })
+                    .collect())
+            }
+            Formation::Triangle {
+                apex,
+                rows,
+                angle,
+                spacing,
+            } => {
+                let spacing = spacing.unwrap_or(ball_radius * 2.05);
+                let angle = angle.to_radians();
+                let along = Vector2::new(angle.cos(), angle.sin());
+                let across = Vector2::new(-along.y, along.x);
+                let apex = Point2::new(apex[0], apex[1]);
+
+                let mut positions = Vec::new();
+
+                for row in 0..rows {
+                    for i in 0..=row {
+                        let offset = i as f64 - row as f64 / 2.;
+                        positions.push(
+                            apex + along * row as f64 * spacing * 3f64.sqrt() / 2.
+                                + across * offset * spacing,
+                        );
+                    }
+                }
+
+                Ok(positions)
+            }
+            Formation::Circle {
+                center,
+                radius,
+                count,
+            } => Ok((0..count)
+                .map(|i| {
+                    let angle = i as f64 / count as f64 * 2. * PI;
+                    Point2::new(
+                        center[0] + angle.cos() * radius,
+                        center[1] + angle.sin() * radius,
+                    )
+                })
+                .collect()),
+            Formation::Random {
+                center,
+                size,
+                count,
+                seed,
+            } => {
+                let mut rng = Rng::new(seed);
+                let mut positions: Vec<Point2<f64>> = Vec::with_capacity(count);
+                let (half_width, half_height) = (size[0] / 2., size[1] / 2.);
+
+                for _ in 0..count {
+                    let free = (0..RANDOM_ATTEMPTS)
+                        .map(|_| {
+                            Point2::new(
+                                center[0] + rng.range(-half_width, half_width),
+                                center[1] + rng.range(-half_height, half_height),
+                            )
+                        })
+                        .find(|p| {
+                            positions
+                                .iter()
+                                .all(|other| (other - p).norm() > ball_radius * 2.5)
+                        });
+
+                    match free {
+                        Some(p) => positions.push(p),
+                        None => {
+                            return Err(format!(
+                                "{} balls don't fit a {}x{} area",
+                                count, size[0], size[1]
+                            ))
+                        }
+                    }
+                }
+
+                Ok(positions)
+            }
+        }
+    }
+}
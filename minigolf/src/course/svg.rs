@@ -0,0 +1,746 @@
+//! SVG import
+//!
+//! An SVG file is a single hole, in user units. Shapes get their meaning from their Inkscape
+//! label or id, or from those of the closest group (layer) that has a meaningful one. Names
+//! are matched by prefix, so `walls`, `wall-2` and `Walls outer` all mean walls:
+//!
+//! - `wall`: every subpath is a wall, closed ones loop back to their start
+//! - `tee`, `cup`: the center of the shape, a circle sets the cup radius too
+//! - `sand`, `ice`, `hazard`, `zone`: closed shapes with a different surface, `data-friction`
+//!   and `data-restitution` attributes override the defaults
+//! - `ball`, `rack`: extra balls on the hole, at the center of the shape
+//!
+//! Anything else is ignored, as are hidden layers. Curves are flattened into straight walls.
+//! The `data-par` attribute on the root element is required, `data-name` (or `<title>`) and
+//! `data-author` are optional.
+
+use crate::course::file::{
+    default_ball_radius, default_cup_radius, CourseError, CourseFile, HoleFile, RackFile, WallFile,
+    ZoneFile,
+};
+use crate::course::COURSE_FILE_VERSION;
+use crate::physics::Surface;
+use mela::lyon::geom::{
+    point, vector, Angle, Arc, ArcFlags, CubicBezierSegment, Point, QuadraticBezierSegment, SvgArc,
+};
+use std::path::Path;
+
+/// How far flattened curves may stray from the real ones
+pub const FLATTEN_TOLERANCE: f64 = 0.25;
+
+const INKSCAPE_NAMESPACE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+pub fn parse_svg(path: &Path, source: &str) -> Result<CourseFile, CourseError> {
+    let document = roxmltree::Document::parse(source).map_err(|e| {
+        let pos = e.pos();
+        let location = format!(" at {}:{}", pos.row, pos.col);
+
+        CourseError::Syntax {
+            path: path.to_owned(),
+            line: pos.row as usize,
+            column: pos.col as usize,
+            message: e.to_string().replacen(&location, "", 1),
+        }
+    })?;
+
+    let root = document.root_element();
+    let invalid = |message: String| CourseError::Invalid {
+        path: path.to_owned(),
+        hole: None,
+        message,
+    };
+
+    let title = root
+        .children()
+        .find(|n| n.has_tag_name("title"))
+        .and_then(|n| n.text());
+    let name = root
+        .attribute("data-name")
+        .or(title)
+        .map(|name| name.trim().to_owned())
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .map_or("Untitled".to_owned(), |s| s.to_string_lossy().into_owned())
+        });
+
+    let par = match root.attribute("data-par") {
+        Some(_) => number(path, root, "data-par")?,
+        None => return Err(invalid("attribute `data-par` is missing".to_owned())),
+    };
+
+    let mut importer = Importer {
+        path,
+        hole: HoleFile {
+            name: name.clone(),
+            par: par as u32,
+            tee: None,
+            cup: [0., 0.],
+            cup_radius: default_cup_radius(),
+            ball_radius: default_ball_radius(),
+            walls: Vec::new(),
+            zones: Vec::new(),
+            teleporters: Vec::new(),
+            gates: Vec::new(),
+            triggers: Vec::new(),
+            rack: Vec::new(),
+        },
+        tee: None,
+        cup: None,
+    };
+
+    let context = Context {
+        role: None,
+        transform: Transform::IDENTITY,
+    };
+
+    for child in root.children().filter(|n| n.is_element()) {
+        importer.element(child, &context)?;
+    }
+
+    let mut hole = importer.hole;
+    hole.tee = Some(
+        importer
+            .tee
+            .ok_or_else(|| invalid("drawing has no tee".to_owned()))?,
+    );
+    hole.cup = importer
+        .cup
+        .ok_or_else(|| invalid("drawing has no cup".to_owned()))?;
+
+    Ok(CourseFile {
+        version: COURSE_FILE_VERSION,
+        name,
+        author: root.attribute("data-author").map(str::to_owned),
+        holes: vec![hole],
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Role {
+    Wall,
+    Tee,
+    Cup,
+    Zone(Surface),
+    Ball,
+}
+
+impl Role {
+    fn from_name(name: &str) -> Option<Role> {
+        let name = name.trim().to_lowercase();
+        let is = |prefix: &str| name.starts_with(prefix);
+
+        if is("wall") {
+            Some(Role::Wall)
+        } else if is("tee") {
+            Some(Role::Tee)
+        } else if is("cup") {
+            Some(Role::Cup)
+        } else if is("sand") {
+            Some(Role::Zone(Surface {
+                friction: 3.,
+                restitution: 0.5,
+            }))
+        } else if is("ice") {
+            Some(Role::Zone(Surface {
+                friction: 0.3,
+                ..Surface::default()
+            }))
+        } else if is("hazard") || is("zone") {
+            Some(Role::Zone(Surface::default()))
+        } else if is("ball") || is("rack") {
+            Some(Role::Ball)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Context {
+    role: Option<Role>,
+    transform: Transform,
+}
+
+/// Affine transform, in the same order as SVG `matrix(a b c d e f)`
+#[derive(Clone, Copy, Debug)]
+struct Transform([f64; 6]);
+
+impl Transform {
+    const IDENTITY: Transform = Transform([1., 0., 0., 1., 0., 0.]);
+
+    /// Applies `inner` first, then `self`
+    fn then(&self, inner: &Transform) -> Transform {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = inner.0;
+
+        Transform([
+            a * a2 + c * b2,
+            b * a2 + d * b2,
+            a * c2 + c * d2,
+            b * c2 + d * d2,
+            a * e2 + c * f2 + e,
+            b * e2 + d * f2 + f,
+        ])
+    }
+
+    fn apply(&self, p: Point<f64>) -> [f64; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        [a * p.x + c * p.y + e, b * p.x + d * p.y + f]
+    }
+
+    /// How much lengths grow, on average
+    fn scale(&self) -> f64 {
+        let [a, b, c, d, _, _] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+
+    fn parse(source: &str) -> Result<Transform, String> {
+        let mut transform = Transform::IDENTITY;
+        let mut rest = source.trim();
+
+        while !rest.is_empty() {
+            let open = rest
+                .find('(')
+                .ok_or_else(|| format!("invalid transform: {}", source))?;
+            let close = rest
+                .find(')')
+                .ok_or_else(|| format!("invalid transform: {}", source))?;
+
+            let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+            let args = numbers(&rest[open + 1..close])?;
+
+            let next = match (name, args.as_slice()) {
+                ("matrix", &[a, b, c, d, e, f]) => Transform([a, b, c, d, e, f]),
+                ("translate", &[x]) => Transform([1., 0., 0., 1., x, 0.]),
+                ("translate", &[x, y]) => Transform([1., 0., 0., 1., x, y]),
+                ("scale", &[s]) => Transform([s, 0., 0., s, 0., 0.]),
+                ("scale", &[x, y]) => Transform([x, 0., 0., y, 0., 0.]),
+                ("rotate", &[angle]) => rotation(angle),
+                ("rotate", &[angle, x, y]) => Transform([1., 0., 0., 1., x, y])
+                    .then(&rotation(angle))
+                    .then(&Transform([1., 0., 0., 1., -x, -y])),
+                ("skewX", &[angle]) => Transform([1., 0., angle.to_radians().tan(), 1., 0., 0.]),
+                ("skewY", &[angle]) => Transform([1., angle.to_radians().tan(), 0., 1., 0., 0.]),
+                _ => return Err(format!("invalid transform: {}", source)),
+            };
+
+            transform = transform.then(&next);
+            rest = rest[close + 1..].trim_start();
+        }
+
+        Ok(transform)
+    }
+}
+
+fn rotation(degrees: f64) -> Transform {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Transform([cos, sin, -sin, cos, 0., 0.])
+}
+
+/// List of numbers separated by whitespace and/or commas
+fn numbers(source: &str) -> Result<Vec<f64>, String> {
+    let mut data = PathData::new(source);
+    let mut numbers = Vec::new();
+
+    while !data.at_end() {
+        numbers.push(data.number()?);
+    }
+
+    Ok(numbers)
+}
+
+/// Flattened outline of a shape
+#[derive(Debug)]
+struct Subpath {
+    points: Vec<Point<f64>>,
+    closed: bool,
+}
+
+struct Importer<'a> {
+    path: &'a Path,
+    hole: HoleFile,
+    tee: Option<[f64; 2]>,
+    cup: Option<[f64; 2]>,
+}
+
+impl<'a> Importer<'a> {
+    fn syntax(&self, node: roxmltree::Node, message: String) -> CourseError {
+        syntax(self.path, node, message)
+    }
+
+    fn element(&mut self, node: roxmltree::Node, parent: &Context) -> Result<(), CourseError> {
+        if is_hidden(node) {
+            return Ok(());
+        }
+
+        let mut context = parent.clone();
+
+        if let Some(transform) = node.attribute("transform") {
+            let transform = Transform::parse(transform).map_err(|e| self.syntax(node, e))?;
+            context.transform = context.transform.then(&transform);
+        }
+
+        let name = node
+            .attribute((INKSCAPE_NAMESPACE, "label"))
+            .and_then(Role::from_name)
+            .or_else(|| node.attribute("id").and_then(Role::from_name));
+
+        if name.is_some() {
+            context.role = name;
+        }
+
+        if let Some(Role::Zone(surface)) = &mut context.role {
+            if node.has_attribute("data-friction") {
+                surface.friction = number(self.path, node, "data-friction")?;
+            }
+
+            if node.has_attribute("data-restitution") {
+                surface.restitution = number(self.path, node, "data-restitution")?;
+            }
+        }
+
+        let path = self.path;
+        let attribute = |name: &str| number(path, node, name);
+
+        // circles are the only shapes that keep their radius
+        let mut circle = None;
+
+        let subpaths = match node.tag_name().name() {
+            "g" | "a" | "switch" => {
+                for child in node.children().filter(|n| n.is_element()) {
+                    self.element(child, &context)?;
+                }
+
+                return Ok(());
+            }
+            "path" => parse_path_data(node.attribute("d").unwrap_or(""), FLATTEN_TOLERANCE)
+                .map_err(|e| self.syntax(node, e))?,
+            "line" => vec![Subpath {
+                points: vec![
+                    point(attribute("x1")?, attribute("y1")?),
+                    point(attribute("x2")?, attribute("y2")?),
+                ],
+                closed: false,
+            }],
+            tag @ "polyline" | tag @ "polygon" => {
+                let coordinates = numbers(node.attribute("points").unwrap_or(""))
+                    .map_err(|e| self.syntax(node, e))?;
+
+                vec![Subpath {
+                    points: coordinates
+                        .chunks_exact(2)
+                        .map(|c| point(c[0], c[1]))
+                        .collect(),
+                    closed: tag == "polygon",
+                }]
+            }
+            "rect" => {
+                let (x, y) = (attribute("x")?, attribute("y")?);
+                let (w, h) = (attribute("width")?, attribute("height")?);
+
+                vec![Subpath {
+                    points: vec![
+                        point(x, y),
+                        point(x + w, y),
+                        point(x + w, y + h),
+                        point(x, y + h),
+                    ],
+                    closed: true,
+                }]
+            }
+            tag @ "circle" | tag @ "ellipse" => {
+                let center = point(attribute("cx")?, attribute("cy")?);
+                let radii = if tag == "circle" {
+                    let r = attribute("r")?;
+                    circle = Some((center, r));
+                    vector(r, r)
+                } else {
+                    vector(attribute("rx")?, attribute("ry")?)
+                };
+
+                let arc = Arc {
+                    center,
+                    radii,
+                    start_angle: Angle::zero(),
+                    sweep_angle: Angle::two_pi(),
+                    x_rotation: Angle::zero(),
+                };
+
+                let mut points = vec![arc.from()];
+                arc.for_each_flattened(FLATTEN_TOLERANCE, &mut |p| points.push(p));
+                points.pop();
+
+                vec![Subpath {
+                    points,
+                    closed: true,
+                }]
+            }
+            _ => return Ok(()),
+        };
+
+        let role = match context.role {
+            Some(role) => role,
+            None => return Ok(()),
+        };
+
+        let transform = &context.transform;
+        let outline = |subpath: &Subpath| -> Vec<[f64; 2]> {
+            subpath.points.iter().map(|p| transform.apply(*p)).collect()
+        };
+
+        let center = || {
+            if let Some((center, _)) = circle {
+                return transform.apply(center);
+            }
+
+            let points: Vec<[f64; 2]> = subpaths.iter().flat_map(outline).collect();
+            let (min, max) = points.iter().fold(
+                ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+                |(min, max), p| {
+                    (
+                        [min[0].min(p[0]), min[1].min(p[1])],
+                        [max[0].max(p[0]), max[1].max(p[1])],
+                    )
+                },
+            );
+
+            [(min[0] + max[0]) / 2., (min[1] + max[1]) / 2.]
+        };
+
+        if subpaths.iter().all(|s| s.points.is_empty()) {
+            return Ok(());
+        }
+
+        match role {
+            Role::Wall => {
+                for subpath in subpaths.iter().filter(|s| s.points.len() >= 2) {
+                    self.hole.walls.push(WallFile {
+                        points: outline(subpath),
+                        closed: subpath.closed,
+                        motion: None,
+                        one_way: false,
+                    });
+                }
+            }
+            Role::Zone(surface) => {
+                for subpath in &subpaths {
+                    if !subpath.closed || subpath.points.len() < 3 {
+                        return Err(self.syntax(node, "zones must be closed shapes".to_owned()));
+                    }
+
+                    self.hole.zones.push(ZoneFile {
+                        polygon: outline(subpath),
+                        friction: surface.friction,
+                        restitution: surface.restitution,
+                    });
+                }
+            }
+            Role::Tee => {
+                if self.tee.replace(center()).is_some() {
+                    return Err(self.syntax(node, "second tee".to_owned()));
+                }
+            }
+            Role::Cup => {
+                if self.cup.replace(center()).is_some() {
+                    return Err(self.syntax(node, "second cup".to_owned()));
+                }
+
+                if let Some((_, radius)) = circle {
+                    self.hole.cup_radius = radius * transform.scale();
+                }
+            }
+            Role::Ball => self.hole.rack.push(RackFile::Position(center())),
+        }
+
+        Ok(())
+    }
+}
+
+fn is_hidden(node: roxmltree::Node) -> bool {
+    let style = node.attribute("style").unwrap_or("");
+
+    node.attribute("display") == Some("none")
+        || style
+            .split(';')
+            .any(|s| s.replace(' ', "").eq_ignore_ascii_case("display:none"))
+}
+
+fn syntax(path: &Path, node: roxmltree::Node, message: String) -> CourseError {
+    let pos = node.document().text_pos_at(node.range().start);
+
+    CourseError::Syntax {
+        path: path.to_owned(),
+        line: pos.row as usize,
+        column: pos.col as usize,
+        message,
+    }
+}
+
+/// Attributes that are missing are 0, like in SVG
+fn number(path: &Path, node: roxmltree::Node, name: &str) -> Result<f64, CourseError> {
+    let value = node.attribute(name).unwrap_or("0");
+
+    // lengths are always in user units, so "px" is the only unit we accept
+    value.trim().trim_end_matches("px").parse().map_err(|_| {
+        syntax(
+            path,
+            node,
+            format!("attribute `{}` is not a number: {}", name, value),
+        )
+    })
+}
+
+/// Tokenizer for path data, which packs numbers as tightly as it can: `M1.5.5-2e1` is `M 1.5 0.5 -20`
+struct PathData<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> PathData<'a> {
+    fn new(source: &'a str) -> PathData<'a> {
+        PathData {
+            source,
+            position: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn skip_separators(&mut self) {
+        let rest = self.rest();
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        self.position += rest.len() - trimmed.len();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.rest().is_empty()
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+
+        match self.rest().as_bytes().first() {
+            Some(c) if c.is_ascii_alphabetic() && !matches!(c, b'e' | b'E') => {
+                self.position += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        self.skip_separators();
+
+        let bytes = self.rest().as_bytes();
+        let mut end = 0;
+        let mut seen_dot = false;
+        let mut seen_exponent = false;
+
+        while end < bytes.len() {
+            match bytes[end] {
+                b'+' | b'-' if end == 0 || matches!(bytes[end - 1], b'e' | b'E') => (),
+                b'0'..=b'9' => (),
+                b'.' if !seen_dot && !seen_exponent => seen_dot = true,
+                b'e' | b'E' if !seen_exponent && end > 0 => seen_exponent = true,
+                _ => break,
+            }
+
+            end += 1;
+        }
+
+        let token = &self.rest()[..end];
+        let number = token
+            .parse()
+            .map_err(|_| format!("expected a number at `{}`", self.rest()))?;
+
+        self.position += end;
+        Ok(number)
+    }
+
+    /// Arc flags don't need separators either: `a1 1 0 011 1` has both flags set
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+
+        let flag = match self.rest().as_bytes().first() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(format!("expected a flag at `{}`", self.rest())),
+        };
+
+        self.position += 1;
+        Ok(flag)
+    }
+
+    fn point(&mut self, relative_to: Option<Point<f64>>) -> Result<Point<f64>, String> {
+        let p = point(self.number()?, self.number()?);
+
+        Ok(match relative_to {
+            Some(origin) => origin + p.to_vector(),
+            None => p,
+        })
+    }
+}
+
+struct PathBuilder {
+    subpaths: Vec<Subpath>,
+    current: Point<f64>,
+    start: Point<f64>,
+}
+
+impl PathBuilder {
+    fn move_to(&mut self, p: Point<f64>) {
+        self.subpaths.push(Subpath {
+            points: vec![p],
+            closed: false,
+        });
+        self.current = p;
+        self.start = p;
+    }
+
+    fn line_to(&mut self, p: Point<f64>) {
+        // drawing after a close starts a new subpath from where the last one started
+        match self.subpaths.last() {
+            Some(subpath) if !subpath.closed => (),
+            _ => self.move_to(self.current),
+        }
+
+        self.subpaths.last_mut().unwrap().points.push(p);
+        self.current = p;
+    }
+
+    fn close(&mut self) {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            // the closing wall is implied, a point on top of the first one would be a zero
+            // length wall
+            if subpath.points.len() > 1 && subpath.points.last() == subpath.points.first() {
+                subpath.points.pop();
+            }
+
+            subpath.closed = true;
+        }
+
+        self.current = self.start;
+    }
+}
+
+fn parse_path_data(source: &str, tolerance: f64) -> Result<Vec<Subpath>, String> {
+    let mut data = PathData::new(source);
+    let mut path = PathBuilder {
+        subpaths: Vec::new(),
+        current: point(0., 0.),
+        start: point(0., 0.),
+    };
+
+    let mut command = None;
+    // control point of the previous curve, which smooth curves mirror
+    let mut last_cubic: Option<Point<f64>> = None;
+    let mut last_quadratic: Option<Point<f64>> = None;
+
+    while !data.at_end() {
+        command = match data.command() {
+            Some(c) => Some(c),
+            None if command.is_some() => command,
+            None => return Err(format!("expected a command at `{}`", data.rest())),
+        };
+
+        let c = command.unwrap();
+        let current = path.current;
+        let origin = if c.is_ascii_lowercase() {
+            Some(current)
+        } else {
+            None
+        };
+
+        let mut cubic = None;
+        let mut quadratic = None;
+
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                path.move_to(data.point(origin)?);
+                // more coordinates after a move are lines
+                command = Some(if origin.is_some() { b'l' } else { b'L' });
+            }
+            b'L' => path.line_to(data.point(origin)?),
+            b'H' => {
+                let x = data.number()? + origin.map_or(0., |o| o.x);
+                path.line_to(point(x, current.y));
+            }
+            b'V' => {
+                let y = data.number()? + origin.map_or(0., |o| o.y);
+                path.line_to(point(current.x, y));
+            }
+            b'C' | b'S' => {
+                let ctrl1 = if c.to_ascii_uppercase() == b'C' {
+                    data.point(origin)?
+                } else {
+                    last_cubic.map_or(current, |last| current + (current - last))
+                };
+                let ctrl2 = data.point(origin)?;
+                let to = data.point(origin)?;
+
+                CubicBezierSegment {
+                    from: current,
+                    ctrl1,
+                    ctrl2,
+                    to,
+                }
+                .for_each_flattened(tolerance, &mut |p| path.line_to(p));
+
+                cubic = Some(ctrl2);
+            }
+            b'Q' | b'T' => {
+                let ctrl = if c.to_ascii_uppercase() == b'Q' {
+                    data.point(origin)?
+                } else {
+                    last_quadratic.map_or(current, |last| current + (current - last))
+                };
+                let to = data.point(origin)?;
+
+                QuadraticBezierSegment {
+                    from: current,
+                    ctrl,
+                    to,
+                }
+                .for_each_flattened(tolerance, &mut |p| path.line_to(p));
+
+                quadratic = Some(ctrl);
+            }
+            b'A' => {
+                let radii = vector(data.number()?.abs(), data.number()?.abs());
+                let x_rotation = Angle::degrees(data.number()?);
+                let flags = ArcFlags {
+                    large_arc: data.flag()?,
+                    sweep: data.flag()?,
+                };
+                let to = data.point(origin)?;
+
+                if radii.x == 0. || radii.y == 0. {
+                    path.line_to(to);
+                } else {
+                    SvgArc {
+                        from: current,
+                        to,
+                        radii,
+                        x_rotation,
+                        flags,
+                    }
+                    .for_each_flattened(tolerance, &mut |p| path.line_to(p));
+                }
+            }
+            b'Z' => {
+                path.close();
+                // Z takes no arguments, so it can't repeat
+                command = None;
+            }
+            _ => return Err(format!("unknown path command `{}`", c as char)),
+        }
+
+        last_cubic = cubic;
+        last_quadratic = quadratic;
+    }
+
+    Ok(path.subpaths)
+}
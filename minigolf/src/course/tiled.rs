@@ -0,0 +1,498 @@
+//! Tiled map import
+//!
+//! A map is a single hole. Objects get their meaning from their type (class in newer Tiled
+//! versions), or from the name of their layer when the type is empty:
+//!
+//! - `wall`/`walls`: polylines are open walls, polygons and rectangles closed ones
+//! - `tee`: where the player ball starts
+//! - `cup`: an ellipse sets the cup radius too
+//! - `zone`/`zones`: polygons or rectangles, with `friction` and `restitution` properties
+//! - `ball`/`rack`: extra balls on the hole
+//!
+//! Anything else is decoration and ignored. Map properties `name`, `author`, `par`,
+//! `cup_radius` and `ball_radius` fill in the rest of the hole, only `par` is required.
+
+use crate::course::file::{
+    default_ball_radius, default_cup_radius, CourseError, CourseFile, HoleFile, RackFile, WallFile,
+    ZoneFile,
+};
+use crate::course::COURSE_FILE_VERSION;
+use crate::physics::Surface;
+use mela::nalgebra::{Point2, Rotation2, Vector2};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tiled JSON maps can't be told apart from our course files by the extension alone
+pub fn is_tiled_json(source: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Probe {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+    }
+
+    serde_json::from_str::<Probe>(source)
+        .ok()
+        .and_then(|p| p.kind)
+        .map_or(false, |kind| kind == "map")
+}
+
+/// Reads a map saved in Tiled's JSON format (`.json`/`.tmj`)
+pub fn parse_json(path: &Path, source: &str) -> Result<CourseFile, CourseError> {
+    let map: JsonMap = serde_json::from_str(source).map_err(|e| CourseError::syntax(path, e))?;
+
+    let mut objects = Vec::new();
+    collect_json_objects(&map.layers, [0., 0.], &mut objects);
+
+    convert(path, json_properties(&map.properties), objects)
+}
+
+/// Reads a map saved in Tiled's XML format (`.tmx`)
+pub fn parse_tmx(path: &Path, source: &str) -> Result<CourseFile, CourseError> {
+    let document = roxmltree::Document::parse(source).map_err(|e| {
+        let pos = e.pos();
+        // like serde_json, roxmltree puts the position in the message
+        let location = format!(" at {}:{}", pos.row, pos.col);
+
+        CourseError::Syntax {
+            path: path.to_owned(),
+            line: pos.row as usize,
+            column: pos.col as usize,
+            message: e.to_string().replacen(&location, "", 1),
+        }
+    })?;
+
+    let root = document.root_element();
+    let mut objects = Vec::new();
+    collect_tmx_objects(path, root, [0., 0.], &mut objects)?;
+
+    convert(path, tmx_properties(root), objects)
+}
+
+type Properties = HashMap<String, String>;
+
+#[derive(Debug)]
+enum Shape {
+    Rectangle,
+    Point,
+    Ellipse,
+    Polyline(Vec<Point2<f64>>),
+    Polygon(Vec<Point2<f64>>),
+}
+
+/// Object from either format, with the layer offsets already applied
+#[derive(Debug)]
+struct Object {
+    id: u32,
+    role: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Clockwise, in degrees, around `(x, y)`
+    rotation: f64,
+    shape: Shape,
+    properties: Properties,
+}
+
+impl Object {
+    fn transform(&self, x: f64, y: f64) -> Point2<f64> {
+        let rotation = Rotation2::new(self.rotation.to_radians());
+        Point2::new(self.x, self.y) + rotation * Vector2::new(x, y)
+    }
+
+    fn outline(&self) -> Vec<Point2<f64>> {
+        match &self.shape {
+            Shape::Polyline(points) | Shape::Polygon(points) => {
+                points.iter().map(|p| self.transform(p.x, p.y)).collect()
+            }
+            _ => vec![
+                self.transform(0., 0.),
+                self.transform(self.width, 0.),
+                self.transform(self.width, self.height),
+                self.transform(0., self.height),
+            ],
+        }
+    }
+
+    fn center(&self) -> Point2<f64> {
+        match &self.shape {
+            Shape::Point => self.transform(0., 0.),
+            Shape::Polyline(_) | Shape::Polygon(_) => {
+                let outline = self.outline();
+                let sum = outline
+                    .iter()
+                    .fold(Point2::origin(), |sum, p| sum + p.coords);
+                sum / outline.len() as f64
+            }
+            _ => self.transform(self.width / 2., self.height / 2.),
+        }
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>, String> {
+        number(&self.properties, key).map_err(|e| format!("object {}: {}", self.id, e))
+    }
+}
+
+fn number(properties: &Properties, key: &str) -> Result<Option<f64>, String> {
+    match properties.get(key) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("property `{}` is not a number: {}", key, value)),
+        None => Ok(None),
+    }
+}
+
+fn array(p: &Point2<f64>) -> [f64; 2] {
+    [p.x, p.y]
+}
+
+fn convert(
+    path: &Path,
+    properties: Properties,
+    objects: Vec<Object>,
+) -> Result<CourseFile, CourseError> {
+    let invalid = |message: String| CourseError::Invalid {
+        path: path.to_owned(),
+        hole: None,
+        message,
+    };
+
+    let name = properties.get("name").cloned().unwrap_or_else(|| {
+        path.file_stem()
+            .map_or("Untitled".to_owned(), |s| s.to_string_lossy().into_owned())
+    });
+
+    let par = number(&properties, "par")
+        .map_err(invalid)?
+        .ok_or_else(|| invalid("map property `par` is missing".to_owned()))?;
+
+    let mut hole = HoleFile {
+        name: name.clone(),
+        par: par as u32,
+        tee: None,
+        cup: [0., 0.],
+        cup_radius: number(&properties, "cup_radius")
+            .map_err(invalid)?
+            .unwrap_or_else(default_cup_radius),
+        ball_radius: number(&properties, "ball_radius")
+            .map_err(invalid)?
+            .unwrap_or_else(default_ball_radius),
+        walls: Vec::new(),
+        zones: Vec::new(),
+        teleporters: Vec::new(),
+        gates: Vec::new(),
+        triggers: Vec::new(),
+        rack: Vec::new(),
+    };
+
+    let mut tee = None;
+    let mut cup = None;
+
+    for object in &objects {
+        match object.role.to_lowercase().as_str() {
+            "wall" | "walls" => hole.walls.push(WallFile {
+                points: object.outline().iter().map(array).collect(),
+                closed: !matches!(object.shape, Shape::Polyline(_)),
+                motion: None,
+                one_way: false,
+            }),
+            "tee" => {
+                if tee.replace(object.center()).is_some() {
+                    return Err(invalid(format!("object {}: second tee", object.id)));
+                }
+            }
+            "cup" => {
+                if cup.replace(object.center()).is_some() {
+                    return Err(invalid(format!("object {}: second cup", object.id)));
+                }
+
+                if let Shape::Ellipse = object.shape {
+                    hole.cup_radius = object.width.min(object.height) / 2.;
+                }
+            }
+            "zone" | "zones" => {
+                if let Shape::Polyline(_) | Shape::Point | Shape::Ellipse = object.shape {
+                    return Err(invalid(format!(
+                        "object {}: zones must be polygons or rectangles",
+                        object.id
+                    )));
+                }
+
+                let default = Surface::default();
+
+                hole.zones.push(ZoneFile {
+                    polygon: object.outline().iter().map(array).collect(),
+                    friction: object
+                        .number("friction")
+                        .map_err(invalid)?
+                        .unwrap_or(default.friction),
+                    restitution: object
+                        .number("restitution")
+                        .map_err(invalid)?
+                        .unwrap_or(default.restitution),
+                });
+            }
+            "ball" | "rack" => hole.rack.push(RackFile::Position(array(&object.center()))),
+            _ => (),
+        }
+    }
+
+    hole.tee = Some(array(
+        &tee.ok_or_else(|| invalid("map has no tee".to_owned()))?,
+    ));
+    hole.cup = array(&cup.ok_or_else(|| invalid("map has no cup".to_owned()))?);
+
+    Ok(CourseFile {
+        version: COURSE_FILE_VERSION,
+        name,
+        author: properties.get("author").cloned(),
+        holes: vec![hole],
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonMap {
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonLayer {
+    ObjectGroup {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        offsetx: f64,
+        #[serde(default)]
+        offsety: f64,
+        objects: Vec<JsonObject>,
+    },
+    Group {
+        #[serde(default)]
+        offsetx: f64,
+        #[serde(default)]
+        offsety: f64,
+        layers: Vec<JsonLayer>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonObject {
+    id: u32,
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default)]
+    point: bool,
+    #[serde(default)]
+    ellipse: bool,
+    polyline: Option<Vec<JsonPoint>>,
+    polygon: Option<Vec<JsonPoint>>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPoint {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn json_properties(properties: &[JsonProperty]) -> Properties {
+    properties
+        .iter()
+        .map(|p| {
+            let value = match &p.value {
+                serde_json::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+
+            (p.name.clone(), value)
+        })
+        .collect()
+}
+
+fn json_points(points: &[JsonPoint]) -> Vec<Point2<f64>> {
+    points.iter().map(|p| Point2::new(p.x, p.y)).collect()
+}
+
+fn collect_json_objects(layers: &[JsonLayer], offset: [f64; 2], objects: &mut Vec<Object>) {
+    for layer in layers {
+        match layer {
+            JsonLayer::ObjectGroup {
+                name,
+                offsetx,
+                offsety,
+                objects: layer_objects,
+            } => {
+                for object in layer_objects {
+                    let shape = if let Some(points) = &object.polyline {
+                        Shape::Polyline(json_points(points))
+                    } else if let Some(points) = &object.polygon {
+                        Shape::Polygon(json_points(points))
+                    } else if object.point {
+                        Shape::Point
+                    } else if object.ellipse {
+                        Shape::Ellipse
+                    } else {
+                        Shape::Rectangle
+                    };
+
+                    objects.push(Object {
+                        id: object.id,
+                        role: if object.kind.is_empty() {
+                            name.clone()
+                        } else {
+                            object.kind.clone()
+                        },
+                        x: object.x + offset[0] + offsetx,
+                        y: object.y + offset[1] + offsety,
+                        width: object.width,
+                        height: object.height,
+                        rotation: object.rotation,
+                        shape,
+                        properties: json_properties(&object.properties),
+                    });
+                }
+            }
+            JsonLayer::Group {
+                offsetx,
+                offsety,
+                layers,
+            } => collect_json_objects(layers, [offset[0] + offsetx, offset[1] + offsety], objects),
+            JsonLayer::Other => (),
+        }
+    }
+}
+
+fn tmx_properties(node: roxmltree::Node) -> Properties {
+    node.children()
+        .filter(|n| n.has_tag_name("properties"))
+        .flat_map(|n| n.children().filter(|n| n.has_tag_name("property")))
+        .filter_map(|p| {
+            let name = p.attribute("name")?;
+            // multiline strings are stored as text instead of an attribute
+            let value = p.attribute("value").or_else(|| p.text())?;
+
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+fn tmx_number(path: &Path, node: roxmltree::Node, name: &str) -> Result<f64, CourseError> {
+    match node.attribute(name) {
+        Some(value) => value.parse().map_err(|_| {
+            let pos = node.document().text_pos_at(node.range().start);
+
+            CourseError::Syntax {
+                path: path.to_owned(),
+                line: pos.row as usize,
+                column: pos.col as usize,
+                message: format!("attribute `{}` is not a number: {}", name, value),
+            }
+        }),
+        None => Ok(0.),
+    }
+}
+
+fn tmx_points(path: &Path, node: roxmltree::Node) -> Result<Vec<Point2<f64>>, CourseError> {
+    let points = node.attribute("points").unwrap_or("");
+
+    points
+        .split_whitespace()
+        .map(|pair| {
+            let mut coordinates = pair.split(',').map(|c| c.parse::<f64>());
+
+            match (coordinates.next(), coordinates.next(), coordinates.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok(Point2::new(x, y)),
+                _ => {
+                    let pos = node.document().text_pos_at(node.range().start);
+
+                    Err(CourseError::Syntax {
+                        path: path.to_owned(),
+                        line: pos.row as usize,
+                        column: pos.col as usize,
+                        message: format!("invalid point: {}", pair),
+                    })
+                }
+            }
+        })
+        .collect()
+}
+
+fn collect_tmx_objects(
+    path: &Path,
+    parent: roxmltree::Node,
+    offset: [f64; 2],
+    objects: &mut Vec<Object>,
+) -> Result<(), CourseError> {
+    for layer in parent.children().filter(|n| n.is_element()) {
+        let offset = [
+            offset[0] + tmx_number(path, layer, "offsetx")?,
+            offset[1] + tmx_number(path, layer, "offsety")?,
+        ];
+
+        match layer.tag_name().name() {
+            "objectgroup" => {
+                let layer_name = layer.attribute("name").unwrap_or("");
+
+                for object in layer.children().filter(|n| n.has_tag_name("object")) {
+                    let child = |tag: &str| object.children().find(|n| n.has_tag_name(tag));
+
+                    let shape = if let Some(node) = child("polyline") {
+                        Shape::Polyline(tmx_points(path, node)?)
+                    } else if let Some(node) = child("polygon") {
+                        Shape::Polygon(tmx_points(path, node)?)
+                    } else if child("point").is_some() {
+                        Shape::Point
+                    } else if child("ellipse").is_some() {
+                        Shape::Ellipse
+                    } else {
+                        Shape::Rectangle
+                    };
+
+                    let kind = object
+                        .attribute("type")
+                        .or_else(|| object.attribute("class"))
+                        .unwrap_or("");
+
+                    objects.push(Object {
+                        id: tmx_number(path, object, "id")? as u32,
+                        role: if kind.is_empty() { layer_name } else { kind }.to_owned(),
+                        x: tmx_number(path, object, "x")? + offset[0],
+                        y: tmx_number(path, object, "y")? + offset[1],
+                        width: tmx_number(path, object, "width")?,
+                        height: tmx_number(path, object, "height")?,
+                        rotation: tmx_number(path, object, "rotation")?,
+                        shape,
+                        properties: tmx_properties(object),
+                    });
+                }
+            }
+            "group" => collect_tmx_objects(path, layer, offset, objects)?,
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,520 @@
+//! Course lints, run with `minigolf --validate <course>` or from the editor
+//!
+//! Balls collide with walls at their center, so anything narrower than a ball that doesn't
+//! look like a way through still is one.
+
+use crate::course::{Course, Hole};
+use crate::physics::{polygon_contains, Ball, Fixtures, PhysicsBody, Snapshot, Surface, Wall};
+use crate::score::holed_at;
+use api::MAX_PUTT_IMPULSE;
+use mela::nalgebra::{Point2, Vector2};
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::fmt;
+use std::rc::Rc;
+
+/// Distances shorter than this are treated as zero
+const EPSILON: f64 = 0.01;
+/// Rays cast from the tee, cup and balls to see if walls enclose them
+const ENCLOSURE_RAYS: usize = 180;
+/// Shot directions and strengths tried from each position when looking for a way to the cup
+const SAMPLE_DIRECTIONS: usize = 24;
+const SAMPLE_POWERS: [f64; 4] = [0.1, 0.25, 0.5, 1.];
+/// Rest positions followed up on after each stroke, the ones closest to the cup
+const SAMPLE_POSITIONS: usize = 8;
+/// Snapshots simulated per sampled shot, shots that take longer are dropped
+const SAMPLE_SNAPSHOT_LIMIT: usize = 256;
+/// Strokes over par tried before giving up on reaching the cup
+const SAMPLE_EXTRA_STROKES: u32 = 2;
+
+#[derive(Clone, Debug)]
+pub struct Warning {
+    /// Index of the hole
+    pub hole: usize,
+    pub position: Point2<f64>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hole {} at ({:.1}, {:.1}): {}",
+            self.hole + 1,
+            self.position.x,
+            self.position.y,
+            self.message
+        )
+    }
+}
+
+pub fn validate(course: &Course) -> Vec<Warning> {
+    course
+        .holes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, hole)| validate_hole(i, hole))
+        .collect()
+}
+
+/// All the checks, including the slow reachability search
+pub fn validate_hole(index: usize, hole: &Hole) -> Vec<Warning> {
+    let mut warnings = lint_hole(index, hole);
+
+    if needs_reachability(hole, &warnings) {
+        warnings.extend(check_reachable(index, hole));
+    }
+
+    warnings
+}
+
+/// Is the reachability search worth running after the lints found `warnings`. If the tee is
+/// already flagged, sampled shots would only tell us the same thing again.
+pub fn needs_reachability(hole: &Hole, warnings: &[Warning]) -> bool {
+    warnings.iter().all(|w| w.position != hole.tee)
+}
+
+fn closest_point(p: &Point2<f64>, wall: &Wall<f64>) -> Point2<f64> {
+    let along = &wall.end - &wall.start;
+
+    if along.norm_squared() <= 0. {
+        return wall.start;
+    }
+
+    let t = ((p - &wall.start).dot(&along) / along.norm_squared())
+        .max(0.)
+        .min(1.);
+    &wall.start + along * t
+}
+
+/// Distance along the ray to where it hits the wall
+fn ray_hit(origin: &Point2<f64>, direction: &Vector2<f64>, wall: &Wall<f64>) -> Option<f64> {
+    let along = &wall.end - &wall.start;
+    let denominator = direction.perp(&along);
+
+    if denominator.abs() <= f64::EPSILON {
+        return None;
+    }
+
+    let to_wall = &wall.start - origin;
+    let t = to_wall.perp(&along) / denominator;
+    let u = to_wall.perp(direction) / denominator;
+
+    if t > 0. && u >= 0. && u <= 1. {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+struct Lints<'a> {
+    index: usize,
+    hole: &'a Hole,
+    /// Every wall segment, with the index of the wall it belongs to
+    segments: Vec<(usize, Wall<f64>)>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Lints<'a> {
+    fn warn(&mut self, position: Point2<f64>, message: String) {
+        self.warnings.push(Warning {
+            hole: self.index,
+            position,
+            message,
+        });
+    }
+
+    fn segments(&mut self) {
+        for i in 0..self.segments.len() {
+            let (wall, segment) = self.segments[i].clone();
+
+            if (&segment.end - &segment.start).norm() <= EPSILON {
+                self.warn(
+                    segment.start,
+                    format!("wall {} has a zero length segment", wall + 1),
+                );
+                continue;
+            }
+
+            for (other_wall, other) in self.segments[i + 1..].to_vec() {
+                let same = |a: &Point2<f64>, b: &Point2<f64>| (a - b).norm() <= EPSILON;
+
+                if (same(&segment.start, &other.start) && same(&segment.end, &other.end))
+                    || (same(&segment.start, &other.end) && same(&segment.end, &other.start))
+                {
+                    let middle = Point2::from((segment.start.coords + segment.end.coords) / 2.);
+
+                    self.warn(
+                        middle,
+                        format!(
+                            "walls {} and {} have the same segment",
+                            wall + 1,
+                            other_wall + 1
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Points where two different walls meet, used to tell gaps apart from corners
+    fn touch_points(&self, a: usize, b: usize) -> Vec<Point2<f64>> {
+        let walls = &self.hole.walls;
+        let ends = |wall: usize| {
+            let points = &walls[wall].points;
+            points.first().into_iter().chain(points.last()).cloned()
+        };
+
+        let mut touches = Vec::new();
+
+        for (from, to) in [(a, b), (b, a)].iter() {
+            for end in ends(*from) {
+                let on_other = self
+                    .segments
+                    .iter()
+                    .filter(|(wall, _)| wall == to)
+                    .any(|(_, segment)| (closest_point(&end, segment) - end).norm() <= EPSILON);
+
+                if on_other {
+                    touches.push(end);
+                }
+            }
+        }
+
+        touches
+    }
+
+    fn gaps(&mut self) {
+        let diameter = self.hole.ball_radius * 2.;
+        let walls = &self.hole.walls;
+        let mut gaps = Vec::new();
+
+        for a in 0..walls.len() {
+            for b in a + 1..walls.len() {
+                if walls[a].motion.is_some() || walls[b].motion.is_some() {
+                    continue;
+                }
+
+                let touches = self.touch_points(a, b);
+                let mut narrowest: Option<(f64, Point2<f64>)> = None;
+
+                let pairs = walls[a]
+                    .points
+                    .iter()
+                    .map(|p| (p, b))
+                    .chain(walls[b].points.iter().map(|p| (p, a)));
+
+                for (vertex, other) in pairs {
+                    // walls that meet get close to each other around the corner
+                    if touches.iter().any(|t| (t - vertex).norm() < diameter * 2.) {
+                        continue;
+                    }
+
+                    for (_, segment) in self.segments.iter().filter(|(w, _)| *w == other) {
+                        let closest = closest_point(vertex, segment);
+                        let distance = (closest - vertex).norm();
+
+                        if distance > EPSILON
+                            && distance < diameter
+                            && narrowest.map_or(true, |(d, _)| distance < d)
+                        {
+                            let middle = Point2::from((vertex.coords + closest.coords) / 2.);
+                            narrowest = Some((distance, middle));
+                        }
+                    }
+                }
+
+                // gaps out of reach don't matter
+                match narrowest {
+                    Some((distance, middle)) if self.walled_off(&middle).is_none() => {
+                        gaps.push((a, b, distance, middle))
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for (a, b, distance, middle) in gaps {
+            self.warn(
+                middle,
+                format!(
+                    "gap of {:.1} between walls {} and {} is narrower than a ball, balls slip through it",
+                    distance,
+                    a + 1,
+                    b + 1
+                ),
+            );
+        }
+    }
+
+    /// Direction balls can roll off to infinity in, if any
+    fn escape(&self, p: &Point2<f64>) -> Option<Vector2<f64>> {
+        (0..ENCLOSURE_RAYS)
+            .map(|i| {
+                let angle = i as f64 / ENCLOSURE_RAYS as f64 * 2. * PI;
+                Vector2::new(angle.cos(), angle.sin())
+            })
+            .find(|direction| {
+                self.segments
+                    .iter()
+                    .all(|(_, segment)| ray_hit(p, direction, segment).is_none())
+            })
+    }
+
+    /// Which closed walls `p` is inside of, points on the same side of every closed wall as
+    /// the tee are in the playable area
+    fn region(&self, p: &Point2<f64>) -> Vec<bool> {
+        self.hole
+            .walls
+            .iter()
+            .map(|w| {
+                w.closed
+                    && w.motion.is_none()
+                    && w.points.len() > 2
+                    && polygon_contains(&w.points, p)
+            })
+            .collect()
+    }
+
+    /// Regions balls can get to from the tee, going through teleporters
+    fn playable_regions(&self) -> Vec<Vec<bool>> {
+        let mut regions = vec![self.region(&self.hole.tee)];
+        let mut grew = true;
+
+        while grew {
+            grew = false;
+
+            for teleporter in &self.hole.teleporters {
+                let exit = self.region(&teleporter.exit);
+
+                if regions.contains(&self.region(&teleporter.entry)) && !regions.contains(&exit) {
+                    regions.push(exit);
+                    grew = true;
+                }
+            }
+        }
+
+        regions
+    }
+
+    /// Closed wall between `p` and the tee, if any
+    fn walled_off(&self, p: &Point2<f64>) -> Option<usize> {
+        let region = self.region(p);
+
+        if self.playable_regions().contains(&region) {
+            return None;
+        }
+
+        self.region(&self.hole.tee)
+            .into_iter()
+            .zip(region)
+            .position(|(tee, p)| tee != p)
+    }
+
+    fn playable_area(&mut self) {
+        let hole = self.hole;
+        let mut points = vec![("tee".to_owned(), hole.tee), ("cup".to_owned(), hole.cup)];
+        points.extend(
+            hole.rack
+                .iter()
+                .enumerate()
+                .map(|(i, b)| (format!("ball {}", i + 1), b.position)),
+        );
+
+        for (i, teleporter) in hole.teleporters.iter().enumerate() {
+            points.push((format!("teleporter {} entry", i + 1), teleporter.entry));
+            points.push((format!("teleporter {} exit", i + 1), teleporter.exit));
+        }
+
+        for (name, p) in points {
+            if let Some(direction) = self.escape(&p) {
+                let far = p + direction * 100.;
+
+                self.warn(
+                    p,
+                    format!(
+                        "{} is not enclosed by walls, balls can roll off towards ({:.0}, {:.0})",
+                        name, far.x, far.y
+                    ),
+                );
+            } else if let Some(wall) = self.walled_off(&p) {
+                self.warn(
+                    p,
+                    format!("{} is walled off from the tee by wall {}", name, wall + 1),
+                );
+            }
+        }
+    }
+
+    fn balls(&mut self) {
+        let hole = self.hole;
+        let balls: Vec<(Point2<f64>, f64)> = std::iter::once((hole.tee, hole.ball_radius))
+            .chain(hole.rack.iter().map(|b| (b.position, b.radius)))
+            .collect();
+
+        let name = |i: usize| match i {
+            0 => "player ball".to_owned(),
+            i => format!("ball {}", i),
+        };
+
+        for (i, (ball, radius)) in balls.iter().enumerate() {
+            let touching = self
+                .segments
+                .iter()
+                .find(|(_, segment)| (closest_point(ball, segment) - ball).norm() < *radius)
+                .map(|(wall, _)| *wall);
+
+            if let Some(wall) = touching {
+                let message = format!("{} overlaps wall {}", name(i), wall + 1);
+                self.warn(*ball, message);
+            }
+
+            for (j, (other, other_radius)) in balls.iter().enumerate().skip(i + 1) {
+                if (other - ball).norm() < radius + other_radius {
+                    let middle = Point2::from((ball.coords + other.coords) / 2.);
+                    self.warn(middle, format!("{} overlaps {}", name(i), name(j)));
+                }
+            }
+        }
+    }
+}
+
+/// The quick checks, only looking at the geometry
+pub fn lint_hole(index: usize, hole: &Hole) -> Vec<Warning> {
+    // moving walls are never in the same place for long, only the static ones are checked
+    let mut lints = Lints {
+        index,
+        hole,
+        segments: hole
+            .walls
+            .iter()
+            .enumerate()
+            .filter(|(_, wall)| wall.motion.is_none())
+            .flat_map(|(i, wall)| wall.segments().into_iter().map(move |s| (i, s)))
+            .collect(),
+        warnings: Vec::new(),
+    };
+
+    lints.segments();
+    lints.gaps();
+    lints.playable_area();
+    lints.balls();
+
+    lints.warnings
+}
+
+enum SampledShot {
+    Holed,
+    Stopped(Point2<f64>),
+    /// Still rolling when the snapshot limit ran out
+    TooLong,
+}
+
+fn sample_shot(
+    hole: &Hole,
+    walls: &Rc<RefCell<Vec<Wall<f64>>>>,
+    fixtures: &Rc<Fixtures>,
+    from: Point2<f64>,
+    impulse: Vector2<f64>,
+) -> SampledShot {
+    let ball = PhysicsBody {
+        body: Ball {
+            radius: hole.ball_radius,
+            mass: 1.,
+        },
+        position: from,
+        velocity: impulse,
+        acceleration: Vector2::new(0., 0.),
+        surface: Surface::default(),
+    };
+
+    let seed = Snapshot::new(vec![ball], Rc::clone(walls)).with_fixtures(Rc::clone(fixtures));
+    let mut snapshots = vec![seed];
+
+    while snapshots.len() < SAMPLE_SNAPSHOT_LIMIT {
+        match snapshots.last_mut().unwrap().next_snapshot() {
+            Some(next) => snapshots.push(next),
+            None => break,
+        }
+    }
+
+    if holed_at(&snapshots, 0, hole).is_some() {
+        return SampledShot::Holed;
+    }
+
+    let last = &snapshots.last().unwrap().balls[0];
+
+    if last.velocity.norm_squared() <= 1. {
+        SampledShot::Stopped(last.position)
+    } else {
+        SampledShot::TooLong
+    }
+}
+
+/// Looks for a way from the tee to the cup by trying a spread of shots from the tee, then from
+/// where the most promising of those stopped, and so on. Other balls are left out, they'd only
+/// slow the search down and get knocked out of the way anyway.
+pub fn check_reachable(index: usize, hole: &Hole) -> Option<Warning> {
+    let walls = Rc::new(RefCell::new(hole.wall_segments()));
+    let fixtures = Rc::new(hole.fixtures());
+    let cell = hole.ball_radius * 4.;
+
+    let mut frontier = vec![hole.tee];
+    let strokes = hole.par + SAMPLE_EXTRA_STROKES;
+
+    for _ in 0..strokes {
+        let mut stopped = Vec::new();
+
+        for from in &frontier {
+            for i in 0..SAMPLE_DIRECTIONS {
+                let angle = i as f64 / SAMPLE_DIRECTIONS as f64 * 2. * PI;
+
+                for &power in SAMPLE_POWERS.iter() {
+                    let impulse = Vector2::new(angle.cos(), angle.sin()) * power * MAX_PUTT_IMPULSE;
+
+                    match sample_shot(hole, &walls, &fixtures, *from, impulse) {
+                        SampledShot::Holed => return None,
+                        SampledShot::Stopped(p) => stopped.push(p),
+                        SampledShot::TooLong => (),
+                    }
+                }
+            }
+        }
+
+        // closest to the cup first, one position per cell
+        stopped.sort_by(|a, b| {
+            let a = (a - hole.cup).norm();
+            let b = (b - hole.cup).norm();
+            a.partial_cmp(&b).unwrap()
+        });
+
+        let mut cells = Vec::new();
+        frontier.clear();
+
+        for p in stopped {
+            let key = ((p.x / cell).floor() as i64, (p.y / cell).floor() as i64);
+
+            if !cells.contains(&key) {
+                cells.push(key);
+                frontier.push(p);
+            }
+
+            if frontier.len() >= SAMPLE_POSITIONS {
+                break;
+            }
+        }
+
+        if frontier.is_empty() {
+            break;
+        }
+    }
+
+    Some(Warning {
+        hole: index,
+        position: hole.cup,
+        message: format!(
+            "no sampled shots reached the cup from the tee in {} strokes",
+            strokes
+        ),
+    })
+}
@@ -7,20 +7,118 @@ use std::time::{Duration, Instant};
 
 use mela;
 use minigolf::Minigolf;
+use resources::Resources;
+use settings::Settings;
+use std::rc::Rc;
 
 use mela::application::Application;
 
+mod clock;
 mod components;
+mod course;
 mod minigolf;
+mod net;
 mod physics;
 mod player;
 mod resources;
+mod rng;
+mod score;
+mod settings;
+mod shot;
 mod states;
 mod world;
-mod api;
+
+/// Prints the problems in the courses, true if there were none
+fn validate(paths: &[String]) -> bool {
+    let mut clean = true;
+
+    for path in paths {
+        match course::Course::load(path) {
+            Ok(course) => {
+                for warning in course::validate(&course) {
+                    println!("{}: {}", path, warning);
+                    clean = false;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                clean = false;
+            }
+        }
+    }
+
+    clean
+}
+
+/// Writes a generated course of `holes` holes to `path`
+fn generate(seed: &str, holes: &str, path: &str) -> Result<(), String> {
+    let seed = seed
+        .parse::<u64>()
+        .map_err(|e| format!("bad seed: {}", e))?;
+    let holes = holes
+        .parse::<usize>()
+        .map_err(|e| format!("bad hole count: {}", e))?;
+
+    let name = format!("Generated {}", seed);
+    let course = course::generate_course(seed, &name, holes, 0);
+
+    course::CourseFile::from(&course)
+        .save(path.as_ref())
+        .map_err(|e| e.to_string())
+}
 
 fn main() {
-    let game = Minigolf::new();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(String::as_str) == Some("--validate") {
+        let clean = validate(&args[1..]);
+        std::process::exit(if clean { 0 } else { 1 });
+    }
+
+    if args.first().map(String::as_str) == Some("--generate") {
+        if let [_, seed, holes, path] = &args[..] {
+            if let Err(e) = generate(seed, holes, path) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+
+            return;
+        }
+
+        eprintln!("usage: minigolf --generate <seed> <holes> <path>");
+        std::process::exit(2);
+    }
+
+    let mut settings = match Settings::load(settings::SETTINGS_PATH.as_ref()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // the command line wins over the environment, which wins over the settings file
+    settings.apply_env();
+
+    if let Err(e) = settings.apply_args(&mut args) {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+
+    let course = args
+        .first()
+        .cloned()
+        .unwrap_or_else(|| resources::DEFAULT_COURSE_PATH.to_owned());
+
+    let resources = match Resources::load(course.as_ref(), settings) {
+        Ok(resources) => resources,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let game = Minigolf::new(Rc::new(resources));
     let app = Application::new(game, "Minigolf");
 
     app.run()
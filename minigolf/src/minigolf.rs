@@ -10,6 +10,8 @@ use mela;
 use mela::game::{IoState, Playable};
 use mela::state::State;
 
+use crate::resources::Resources;
+use crate::score::Round;
 use crate::states::{Play, Wrapper};
 use mela::debug::{DebugContext, DebugDrawable};
 use mela::gfx::RenderContext;
@@ -22,9 +24,11 @@ pub(crate) struct Minigolf {
 }
 
 impl Minigolf {
-    pub fn new() -> Minigolf {
+    pub fn new(resources: Rc<Resources>) -> Minigolf {
+        let round = Round::new(Rc::clone(&resources.course));
+
         Minigolf {
-            state: Wrapper::Play(Play::new()),
+            state: Wrapper::Play(Play::new(resources, round)),
             io_state: Default::default(),
         }
     }
@@ -0,0 +1,399 @@
+//! Lobby server requests on background threads, so the frame never waits for the network
+
+use crate::clock::RoundTrip;
+use api::{GetUpdate, HostCommand, JoinGame, JoinResponse, PublicStates, Putt, PuttError};
+use reqwest::blocking::Client;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// Longest a whole request can take, a stalled one would hold up every request behind it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the push thread checks if it's still wanted
+const PUSH_READ_TIMEOUT: Duration = Duration::from_millis(250);
+/// Wait after the first failure, doubled after each one that follows
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(8);
+/// Failures in a row before a connection is given up on
+const MAX_FAILURES: u32 = 8;
+
+#[derive(Clone, Debug)]
+pub enum ApiError {
+    /// The server couldn't be reached or the connection broke
+    Transport(String),
+    /// The server answered with something other than 200, with the body it sent
+    Status(u16, String),
+    /// The response wasn't what the protocol says it should be
+    Decode(String),
+    /// The response was well formed but made no sense, like a putt for a ball that doesn't exist
+    Protocol(String),
+    /// The server refused a putt
+    Putt(PuttError),
+}
+
+impl ApiError {
+    /// Could trying again help
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Transport(_) => true,
+            ApiError::Status(status, _) => *status >= 500,
+            ApiError::Decode(_) | ApiError::Protocol(_) | ApiError::Putt(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Transport(message) => write!(f, "can't reach the server: {}", message),
+            ApiError::Status(status, body) if body.is_empty() => {
+                write!(f, "server answered {}", status)
+            }
+            ApiError::Status(status, body) => write!(f, "server answered {}: {}", status, body),
+            ApiError::Decode(message) => write!(f, "unexpected response: {}", message),
+            ApiError::Protocol(message) => write!(f, "protocol violation: {}", message),
+            ApiError::Putt(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How a polled connection is doing, for the UI
+#[derive(Clone, Debug)]
+pub enum ConnectionState {
+    Connected,
+    /// The last requests failed, this many in a row
+    Reconnecting(u32),
+    /// Retrying won't help, or has been tried long enough
+    Lost(ApiError),
+}
+
+/// Exponential backoff for retrying idempotent requests
+#[derive(Debug, Default)]
+pub struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    /// How long to wait before the next request, `interval` when nothing has failed
+    pub fn delay(&self, interval: Duration) -> Duration {
+        if self.failures == 0 {
+            return interval;
+        }
+
+        let exponent = (self.failures - 1).min(16);
+        (BACKOFF_BASE * 2u32.pow(exponent)).min(BACKOFF_MAX)
+    }
+
+    pub fn succeeded(&mut self) -> ConnectionState {
+        self.failures = 0;
+        ConnectionState::Connected
+    }
+
+    /// Counts the failure and tells if it's worth trying again
+    pub fn failed(&mut self, error: ApiError) -> ConnectionState {
+        self.failures += 1;
+
+        if error.is_transient() && self.failures < MAX_FAILURES {
+            ConnectionState::Reconnecting(self.failures)
+        } else {
+            ConnectionState::Lost(error)
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce(&Client) + Send>;
+
+/// Result of a request that's still on its way
+pub struct Pending<T> {
+    receiver: Receiver<(Result<T, ApiError>, RoundTrip)>,
+}
+
+impl<T> Pending<T> {
+    /// The result if it has arrived. Each result is given out once, after that the request
+    /// should be dropped.
+    pub fn poll(&self) -> Option<Result<T, ApiError>> {
+        self.poll_timed().map(|(result, _)| result)
+    }
+
+    /// Like `poll`, along with when the request went out and the answer came back
+    pub fn poll_timed(&self) -> Option<(Result<T, ApiError>, RoundTrip)> {
+        match self.receiver.try_recv() {
+            Ok(answer) => Some(answer),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                let now = Instant::now();
+                let error = ApiError::Transport("network worker has stopped".to_owned());
+
+                Some((
+                    Err(error),
+                    RoundTrip {
+                        sent: now,
+                        received: now,
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Lobby states pushed by the server as they change
+pub struct Subscription {
+    receiver: Receiver<Result<(PublicStates, Instant), ApiError>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Subscription {
+    /// The next state pushed by the server and when it arrived, if one has. An error means
+    /// the subscription has ended and updates have to be polled for instead.
+    pub fn poll(&self) -> Option<Result<(PublicStates, Instant), ApiError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(ApiError::Transport(
+                "push connection has closed".to_owned(),
+            ))),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Handle to the network threads. Polls for updates go on a thread of their own, so putts and
+/// other requests never wait behind them. Each thread sends its requests one at a time in the
+/// order they were made.
+#[derive(Clone)]
+pub struct NetWorker {
+    endpoint: String,
+    jobs: Sender<Job>,
+    polls: Sender<Job>,
+}
+
+impl NetWorker {
+    pub fn new(endpoint: String) -> NetWorker {
+        NetWorker {
+            endpoint,
+            jobs: worker("network"),
+            polls: worker("poll"),
+        }
+    }
+
+    pub fn join(&self, join: JoinGame) -> Pending<JoinResponse> {
+        self.request(&self.jobs, Method::POST, join)
+    }
+
+    pub fn update(&self, update: GetUpdate) -> Pending<PublicStates> {
+        self.request(&self.polls, Method::GET, update)
+    }
+
+    pub fn putt(&self, putt: Putt) -> Pending<PublicStates> {
+        self.request(&self.jobs, Method::PUT, putt)
+    }
+
+    pub fn host(&self, command: HostCommand) -> Pending<PublicStates> {
+        self.request(&self.jobs, Method::PATCH, command)
+    }
+
+    /// Opens a WebSocket for the lobby's states on a thread of its own, so it doesn't hold up
+    /// the other requests
+    pub fn subscribe(&self, lobby: usize) -> Subscription {
+        let (sender, receiver) = channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let url = push_url(&self.endpoint, lobby);
+
+        {
+            let closed = Arc::clone(&closed);
+
+            thread::Builder::new()
+                .name("push".to_owned())
+                .spawn(move || {
+                    if let Err(e) = listen(&url, &sender, &closed) {
+                        let _ = sender.send(Err(e));
+                    }
+                })
+                .unwrap();
+        }
+
+        Subscription { receiver, closed }
+    }
+
+    fn request<B, T>(&self, queue: &Sender<Job>, method: Method, body: B) -> Pending<T>
+    where
+        B: Serialize + Send + 'static,
+        T: DeserializeOwned + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let endpoint = self.endpoint.clone();
+
+        let job: Job = Box::new(move |client| {
+            let sent = Instant::now();
+            let result = send(client, method, &endpoint, &body);
+            let received = Instant::now();
+
+            // nobody is waiting if the state that asked has been left
+            let _ = sender.send((result, RoundTrip { sent, received }));
+        });
+
+        // if the worker is gone the receiver reports it on the first poll
+        let _ = queue.send(job);
+
+        Pending { receiver }
+    }
+}
+
+/// Starts a thread that runs the jobs sent to it in order
+fn worker(name: &str) -> Sender<Job> {
+    let (jobs, queue) = channel::<Job>();
+
+    thread::Builder::new()
+        .name(name.to_owned())
+        .spawn(move || {
+            let client = Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap();
+
+            for job in queue {
+                job(&client);
+            }
+        })
+        .unwrap();
+
+    jobs
+}
+
+fn send<B: Serialize, T: DeserializeOwned>(
+    client: &Client,
+    method: Method,
+    endpoint: &str,
+    body: &B,
+) -> Result<T, ApiError> {
+    let resp = client
+        .request(method, endpoint)
+        .json(body)
+        .send()
+        .map_err(|e| ApiError::Transport(e.to_string()))?;
+
+    let status = resp.status();
+    let text = resp
+        .text()
+        .map_err(|e| ApiError::Transport(e.to_string()))?;
+
+    if !status.is_success() {
+        // refused putts say why, anything else is just passed on
+        return Err(match serde_json::from_str(&text) {
+            Ok(e) => ApiError::Putt(e),
+            Err(_) => ApiError::Status(status.as_u16(), text),
+        });
+    }
+
+    serde_json::from_str(&text).map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+/// WebSocket URL for the lobby's pushed states, `http` endpoints become `ws` and `https` `wss`
+fn push_url(endpoint: &str, lobby: usize) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    let endpoint = match endpoint.strip_prefix("http") {
+        Some(rest) => format!("ws{}", rest),
+        None => endpoint.to_owned(),
+    };
+
+    format!("{}/?lobby={}", endpoint, lobby)
+}
+
+fn listen(
+    url: &str,
+    sender: &Sender<Result<(PublicStates, Instant), ApiError>>,
+    closed: &AtomicBool,
+) -> Result<(), ApiError> {
+    let (mut socket, _) =
+        tungstenite::connect(url).map_err(|e| ApiError::Transport(e.to_string()))?;
+
+    socket
+        .get_mut()
+        .set_read_timeout(Some(PUSH_READ_TIMEOUT))
+        .map_err(|e| ApiError::Transport(e.to_string()))?;
+
+    while !closed.load(Ordering::Relaxed) {
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                let received = Instant::now();
+                let state =
+                    serde_json::from_str(&text).map_err(|e| ApiError::Decode(e.to_string()))?;
+
+                if sender.send(Ok((state, received))).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => {
+                return Err(ApiError::Transport(
+                    "server closed the push connection".to_owned(),
+                ))
+            }
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(e) => return Err(ApiError::Transport(e.to_string())),
+        }
+    }
+
+    let _ = socket.close(None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let interval = Duration::from_millis(100);
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.delay(interval), interval);
+
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            backoff.failed(ApiError::Transport("refused".to_owned()));
+            delays.push(backoff.delay(interval).as_millis());
+        }
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 8000]);
+
+        backoff.succeeded();
+        assert_eq!(backoff.delay(interval), interval);
+    }
+
+    #[test]
+    fn backoff_gives_up() {
+        let mut backoff = Backoff::default();
+
+        for failures in 1..MAX_FAILURES {
+            let state = backoff.failed(ApiError::Status(503, String::new()));
+            assert!(matches!(state, ConnectionState::Reconnecting(n) if n == failures));
+        }
+        assert!(matches!(
+            backoff.failed(ApiError::Status(503, String::new())),
+            ConnectionState::Lost(_)
+        ));
+
+        // retrying doesn't help with errors that aren't transient
+        let mut backoff = Backoff::default();
+        assert!(matches!(
+            backoff.failed(ApiError::Decode("not json".to_owned())),
+            ConnectionState::Lost(_)
+        ));
+    }
+}
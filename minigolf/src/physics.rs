@@ -1,5 +1,6 @@
 //! Analytical physics engine
 
+use crate::clock::GameClock;
 use mela::debug::DebugContext;
 use mela::ecs::component::Transform;
 use mela::ecs::system::{Read, Write};
@@ -22,6 +23,11 @@ use std::time::{Duration, Instant};
 
 const EVENT_MARGIN: f64 = 0.001;
 const COLLISION_MARGIN: f64 = 0.0000000000001;
+const WALL_MARGIN: f64 = 0.000001;
+/// Furthest a ball and a moving wall get closer to each other between two checks, in pixels
+const KINEMATIC_STEP: f64 = 2.;
+/// Bisection steps when narrowing down a hit on a moving wall
+const KINEMATIC_ITERATIONS: usize = 40;
 
 #[derive(Clone, Debug)]
 pub struct PhysicsBody<T, N: RealField = f64> {
@@ -29,17 +35,293 @@ pub struct PhysicsBody<T, N: RealField = f64> {
     pub position: na::Point2<N>,
     pub velocity: na::Vector2<N>,
     pub acceleration: na::Vector2<N>,
+    /// Surface the body is currently rolling on
+    pub surface: Surface,
+}
+
+/// Rolling friction and wall bounciness of the ground
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Surface {
+    /// Multiplier for the normal rolling friction
+    pub friction: f64,
+    /// Fraction of speed kept when bouncing off a wall
+    pub restitution: f64,
+}
+
+impl Default for Surface {
+    fn default() -> Surface {
+        Surface {
+            friction: 1.0,
+            restitution: 0.86,
+        }
+    }
+}
+
+/// Area with a different surface, like sand or ice
+#[derive(Clone, Debug)]
+pub struct Zone {
+    pub polygon: Vec<Point2<f64>>,
+    pub surface: Surface,
+}
+
+/// Even-odd rule, the polygon is closed implicitly
+pub fn polygon_contains(polygon: &[Point2<f64>], point: &Point2<f64>) -> bool {
+    let mut inside = false;
+
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+        {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+impl Zone {
+    pub fn contains(&self, point: &Point2<f64>) -> bool {
+        polygon_contains(&self.polygon, point)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = Wall<f64>> + '_ {
+        self.polygon
+            .iter()
+            .zip(self.polygon.iter().cycle().skip(1))
+            .map(|(a, b)| Wall {
+                start: a.clone(),
+                end: b.clone(),
+                one_way: false,
+            })
+    }
+}
+
+/// What a trigger does to its gate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GateAction {
+    Open,
+    Close,
+    Toggle,
+}
+
+impl GateAction {
+    /// Is the gate open after this, `open` being whether it was before
+    pub fn apply(self, open: bool) -> bool {
+        match self {
+            GateAction::Open => true,
+            GateAction::Close => false,
+            GateAction::Toggle => !open,
+        }
+    }
+}
+
+/// Walls that triggers open and close
+#[derive(Clone, Debug)]
+pub struct Gate {
+    pub walls: Vec<Wall<f64>>,
+    /// Is the gate open when the hole starts
+    pub open: bool,
+}
+
+/// Line that opens or closes a gate when a ball crosses it
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pub line: Wall<f64>,
+    /// Balls bounce off switches, instead of rolling over them
+    pub switch: bool,
+    pub gate: usize,
+    pub action: GateAction,
+}
+
+/// Pad that moves balls rolling onto it over to `exit`
+#[derive(Clone, Debug)]
+pub struct Teleporter {
+    pub entry: Point2<f64>,
+    /// Balls are teleported when their center gets this close to `entry`
+    pub radius: f64,
+    pub exit: Point2<f64>,
+    /// Direction balls leave the exit in, in radians. They keep going the way they were going
+    /// if this is not set.
+    pub direction: Option<f64>,
+    /// Multiplier for the speed of teleported balls
+    pub speed: f64,
+}
+
+impl Teleporter {
+    /// Velocity of a ball coming out of the exit, having gone in with `velocity`
+    pub fn exit_velocity(&self, velocity: &Vector2<f64>) -> Vector2<f64> {
+        match self.direction {
+            Some(angle) => Vector2::new(angle.cos(), angle.sin()) * velocity.norm() * self.speed,
+            None => velocity * self.speed,
+        }
+    }
+}
+
+/// Features of a hole other than static walls
+#[derive(Clone, Debug, Default)]
+pub struct Fixtures {
+    pub zones: Vec<Zone>,
+    pub kinematic_walls: Vec<KinematicWall>,
+    pub teleporters: Vec<Teleporter>,
+    pub gates: Vec<Gate>,
+    pub triggers: Vec<Trigger>,
+    zone_edges: Vec<Wall<f64>>,
+    /// Walls of every gate, with the index of the gate they belong to
+    gate_walls: Vec<(usize, Wall<f64>)>,
+}
+
+impl Fixtures {
+    pub fn new(
+        zones: Vec<Zone>,
+        kinematic_walls: Vec<KinematicWall>,
+        teleporters: Vec<Teleporter>,
+        gates: Vec<Gate>,
+        triggers: Vec<Trigger>,
+    ) -> Fixtures {
+        let zone_edges = zones.iter().flat_map(|z| z.edges()).collect();
+        let gate_walls = gates
+            .iter()
+            .enumerate()
+            .flat_map(|(i, g)| g.walls.iter().map(move |w| (i, w.clone())))
+            .collect();
+
+        Fixtures {
+            zones,
+            kinematic_walls,
+            teleporters,
+            gates,
+            triggers,
+            zone_edges,
+            gate_walls,
+        }
+    }
+
+    /// Surface at `point`, later zones are on top of earlier ones
+    pub fn surface_at(&self, point: &Point2<f64>) -> Surface {
+        self.zones
+            .iter()
+            .rev()
+            .find(|z| z.contains(point))
+            .map(|z| z.surface)
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Wall<N: RealField = f64> {
     pub start: Point2<N>,
     pub end: Point2<N>,
+    /// Balls only bounce off the left side of one way walls, looking from `start` to `end`,
+    /// and roll through from the right
+    pub one_way: bool,
+}
+
+/// Periodic path of a moving wall, as a function of the time since the hole started
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Motion {
+    /// Spins around `pivot`, `speed` in radians per second
+    Rotate { pivot: Point2<f64>, speed: f64 },
+    /// Slides to `offset` and back once every `period` seconds, starting `phase` periods in
+    Slide {
+        offset: Vector2<f64>,
+        period: f64,
+        phase: f64,
+    },
+}
+
+impl Motion {
+    /// Where `point` of the wall at rest is at `time`
+    pub fn transform(&self, point: &Point2<f64>, time: f64) -> Point2<f64> {
+        match *self {
+            Motion::Rotate { pivot, speed } => {
+                let (sin, cos) = (speed * time).sin_cos();
+                let p = point - pivot;
+
+                pivot + Vector2::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos)
+            }
+            Motion::Slide {
+                offset,
+                period,
+                phase,
+            } => {
+                let angle = 2. * std::f64::consts::PI * (time / period + phase);
+                point + offset * (1. - angle.cos()) / 2.
+            }
+        }
+    }
+
+    /// Velocity of the wall at `point` at `time`, `point` being where it is at that time
+    pub fn velocity(&self, point: &Point2<f64>, time: f64) -> Vector2<f64> {
+        match *self {
+            Motion::Rotate { pivot, speed } => {
+                let p = point - pivot;
+                Vector2::new(-p.y, p.x) * speed
+            }
+            Motion::Slide {
+                offset,
+                period,
+                phase,
+            } => {
+                let angle = 2. * std::f64::consts::PI * (time / period + phase);
+                offset * std::f64::consts::PI / period * angle.sin()
+            }
+        }
+    }
+
+    /// Time after which the motion repeats itself
+    pub fn period(&self) -> f64 {
+        match *self {
+            Motion::Rotate { speed, .. } => 2. * std::f64::consts::PI / speed.abs(),
+            Motion::Slide { period, .. } => period,
+        }
+    }
+}
+
+/// Wall moving along a `motion` from its `rest` position
+#[derive(Clone, Debug)]
+pub struct KinematicWall {
+    pub rest: Wall<f64>,
+    pub motion: Motion,
+}
+
+impl KinematicWall {
+    /// Position of the wall at `time`
+    pub fn at(&self, time: f64) -> Wall<f64> {
+        Wall {
+            start: self.motion.transform(&self.rest.start, time),
+            end: self.motion.transform(&self.rest.end, time),
+            one_way: self.rest.one_way,
+        }
+    }
+
+    /// Circle the wall always stays inside of
+    fn bounds(&self) -> (Point2<f64>, f64) {
+        let (start, end) = (&self.rest.start, &self.rest.end);
+
+        match self.motion {
+            Motion::Rotate { pivot, .. } => {
+                (pivot, (start - pivot).norm().max((end - pivot).norm()))
+            }
+            Motion::Slide { offset, .. } => (
+                na::center(start, end) + offset / 2.,
+                (end - start).norm() / 2. + offset.norm() / 2.,
+            ),
+        }
+    }
+
+    /// Fastest any point of the wall ever moves
+    fn max_speed(&self) -> f64 {
+        match self.motion {
+            Motion::Rotate { speed, .. } => speed.abs() * self.bounds().1,
+            Motion::Slide { offset, period, .. } => offset.norm() * std::f64::consts::PI / period,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Ball<N: RealField = f64> {
     pub radius: N,
+    pub mass: N,
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +337,40 @@ pub enum Event {
     BallCollision(usize, usize),
     BallStopped(usize),
     BallStaticCollision(usize, usize, Vector2<f64>),
+    /// Ball hit a moving wall, with the normal of the wall toward the ball and the velocity of
+    /// the wall where it hit
+    BallKinematicCollision(usize, usize, Vector2<f64>, Vector2<f64>),
+    /// Ball crossed an edge of a zone
+    ZoneCrossing(usize, usize),
+    /// Ball rolled onto the entry pad of a teleporter
+    Teleport(usize, usize),
+    /// Ball crossed a trigger, with the normal of it if it was a switch the ball bounced off
+    Trigger(usize, usize, Option<Vector2<f64>>),
+    /// Player hit the ball
+    Putt(usize, Vector2<f64>),
+}
+
+impl Event {
+    /// Is this a collision involving `ball`
+    pub fn is_collision_of(&self, ball: usize) -> bool {
+        match self {
+            Event::BallCollision(a, b) => *a == ball || *b == ball,
+            Event::BallStaticCollision(a, _, _) => *a == ball,
+            Event::BallKinematicCollision(a, _, _, _) => *a == ball,
+            Event::Trigger(a, _, normal) => *a == ball && normal.is_some(),
+            Event::BallStopped(_)
+            | Event::ZoneCrossing(_, _)
+            | Event::Teleport(_, _)
+            | Event::Putt(_, _) => false,
+        }
+    }
+}
+
+/// Predicted path of a ball, see `predict_trajectory`
+#[derive(Clone, Debug, Default)]
+pub struct Trajectory {
+    pub points: Vec<Point2<f64>>,
+    pub collisions: Vec<Point2<f64>>,
 }
 
 #[derive(Clone, Debug)]
@@ -64,8 +380,15 @@ pub struct Snapshot<N: RealField> {
     pub balls: Vec<PhysicsBody<Ball<N>, N>>,
     pub ignore_collisions: Vec<(usize, usize)>,
     pub ignore_wall_collisions: Vec<(usize, usize)>,
+    pub ignore_zone_crossings: Vec<(usize, usize)>,
+    pub ignore_trigger_crossings: Vec<(usize, usize)>,
     pub index: usize,
     pub walls: Rc<RefCell<Vec<Wall>>>,
+    pub fixtures: Rc<Fixtures>,
+    /// Which gates of the fixtures are open
+    pub gates_open: Vec<bool>,
+    /// Events that happened at `start_time`, leading to this snapshot
+    pub events: Vec<Event>,
 }
 
 impl Snapshot<f64> {
@@ -79,9 +402,26 @@ impl Snapshot<f64> {
             balls,
             ignore_collisions: Vec::new(),
             ignore_wall_collisions: Vec::new(),
+            ignore_zone_crossings: Vec::new(),
+            ignore_trigger_crossings: Vec::new(),
             index: 0,
             walls,
+            fixtures: Rc::new(Fixtures::default()),
+            gates_open: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets the fixtures, puts the balls on the surfaces they are on and the gates the way
+    /// they are when the hole starts
+    pub fn with_fixtures(mut self, fixtures: Rc<Fixtures>) -> Snapshot<f64> {
+        for ball in &mut self.balls {
+            ball.surface = fixtures.surface_at(&ball.position);
         }
+
+        self.gates_open = fixtures.gates.iter().map(|g| g.open).collect();
+        self.fixtures = fixtures;
+        self
     }
 
     pub fn ball_pos(&self, ball_index: usize, t: Duration) -> (Point2<f64>, &Ball<f64>) {
@@ -104,6 +444,8 @@ impl Snapshot<f64> {
         // find next collision
         let mut ignored = Vec::new();
         let mut ignored_walls = Vec::new();
+        let mut ignored_zones = Vec::new();
+        let mut ignored_triggers = Vec::new();
         let mut smallest = std::f64::INFINITY;
         let mut events = Vec::new();
 
@@ -122,7 +464,21 @@ impl Snapshot<f64> {
                 events.push(Event::BallStopped(i));
             }
 
-            for (j, wall) in walls.iter().enumerate() {
+            // walls of closed gates are numbered after the static walls
+            let gate_walls = self.fixtures.gate_walls.iter().map(|(gate, wall)| {
+                if self.gates_open[*gate] {
+                    None
+                } else {
+                    Some(wall)
+                }
+            });
+
+            for (j, wall) in walls.iter().map(Some).chain(gate_walls).enumerate() {
+                let wall = match wall {
+                    Some(wall) => wall,
+                    None => continue,
+                };
+
                 if let Some(toi) = self.ball_wall_toi(ball, wall) {
                     let delta = &wall.end - &wall.start;
                     let n1 = Vector2::new(-delta.y, delta.x).normalize();
@@ -130,8 +486,16 @@ impl Snapshot<f64> {
 
                     let n = n2;
 
+                    if wall.one_way && ball.velocity.dot(&n) >= 0. {
+                        continue;
+                    }
+
+                    // only the contact the ball is leaving is ignored, not later hits on the wall
+                    let ignore =
+                        toi <= EVENT_MARGIN && self.ignore_wall_collisions.contains(&(i, j));
+
                     if toi < smallest - EVENT_MARGIN {
-                        if self.ignore_wall_collisions.contains(&(i, j)) {
+                        if ignore {
                             ignored_walls.push((i, j));
                             continue;
                         } else {
@@ -141,7 +505,7 @@ impl Snapshot<f64> {
                             events.push(Event::BallStaticCollision(i, j, n));
                         }
                     } else if (toi - smallest).abs() <= EVENT_MARGIN {
-                        if self.ignore_wall_collisions.contains(&(i, j)) {
+                        if ignore {
                             ignored_walls.push((i, j));
                             continue;
                         } else {
@@ -150,6 +514,62 @@ impl Snapshot<f64> {
                     }
                 }
             }
+
+            for (j, edge) in self.fixtures.zone_edges.iter().enumerate() {
+                if let Some(toi) = self.ball_wall_toi(ball, edge) {
+                    if toi < smallest - EVENT_MARGIN {
+                        if self.ignore_zone_crossings.contains(&(i, j)) {
+                            ignored_zones.push((i, j));
+                            continue;
+                        } else {
+                            smallest = toi;
+                            ignored_zones.clear();
+                            events.clear();
+                            events.push(Event::ZoneCrossing(i, j));
+                        }
+                    } else if (toi - smallest).abs() <= EVENT_MARGIN {
+                        if self.ignore_zone_crossings.contains(&(i, j)) {
+                            ignored_zones.push((i, j));
+                            continue;
+                        } else {
+                            events.push(Event::ZoneCrossing(i, j));
+                        }
+                    }
+                }
+            }
+
+            for (j, trigger) in self.fixtures.triggers.iter().enumerate() {
+                if let Some(toi) = self.ball_wall_toi(ball, &trigger.line) {
+                    let normal = if trigger.switch {
+                        let delta = &trigger.line.end - &trigger.line.start;
+                        Some(Vector2::new(delta.y, -delta.x).normalize())
+                    } else {
+                        None
+                    };
+
+                    let ignore =
+                        toi <= EVENT_MARGIN && self.ignore_trigger_crossings.contains(&(i, j));
+
+                    if toi < smallest - EVENT_MARGIN {
+                        if ignore {
+                            ignored_triggers.push((i, j));
+                            continue;
+                        } else {
+                            smallest = toi;
+                            ignored_triggers.clear();
+                            events.clear();
+                            events.push(Event::Trigger(i, j, normal));
+                        }
+                    } else if (toi - smallest).abs() <= EVENT_MARGIN {
+                        if ignore {
+                            ignored_triggers.push((i, j));
+                            continue;
+                        } else {
+                            events.push(Event::Trigger(i, j, normal));
+                        }
+                    }
+                }
+            }
         }
 
         // let ball_pairs: Vec<((usize, &PhysicsBody<Ball>), (usize, &PhysicsBody<Ball>))> = self
@@ -233,6 +653,50 @@ impl Snapshot<f64> {
             }
         }
 
+        for (i, ball) in self.balls.iter().enumerate() {
+            let horizon = smallest + EVENT_MARGIN;
+            let kinematic =
+                self.fixtures
+                    .kinematic_walls
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, wall)| {
+                        self.ball_kinematic_toi(ball, wall, horizon).map(
+                            |(toi, normal, velocity)| {
+                                (toi, Event::BallKinematicCollision(i, j, normal, velocity))
+                            },
+                        )
+                    });
+            let teleports =
+                self.fixtures
+                    .teleporters
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, teleporter)| {
+                        Self::ball_teleporter_toi(ball, teleporter)
+                            .map(|toi| (toi, Event::Teleport(i, j)))
+                    });
+            let hits: Vec<(f64, Event)> = kinematic.chain(teleports).collect();
+
+            for (toi, event) in hits {
+                if toi < smallest - EVENT_MARGIN {
+                    // anything ignored was touching at the start, and is left behind by now
+                    if toi > EVENT_MARGIN {
+                        ignored.clear();
+                        ignored_walls.clear();
+                        ignored_zones.clear();
+                        ignored_triggers.clear();
+                    }
+
+                    smallest = toi;
+                    events.clear();
+                    events.push(event);
+                } else if (toi - smallest).abs() <= EVENT_MARGIN {
+                    events.push(event);
+                }
+            }
+        }
+
         smallest = smallest.max(0.);
 
         if smallest < std::f64::INFINITY {
@@ -240,6 +704,8 @@ impl Snapshot<f64> {
             let mut new = self.advance_to(smallest);
             new.ignore_collisions = ignored;
             new.ignore_wall_collisions = ignored_walls;
+            new.ignore_zone_crossings = ignored_zones;
+            new.ignore_trigger_crossings = ignored_triggers;
 
             for event in &events {
                 match &event {
@@ -259,13 +725,64 @@ impl Snapshot<f64> {
                     Event::BallStaticCollision(ball, wall, normal) => {
                         new.ignore_wall_collisions.push((*ball, *wall));
                         let ball = &mut new.balls[*ball];
-                        let new_velocity =
-                            &ball.velocity - 2. * &ball.velocity.dot(normal) * normal * 0.86;
+                        let new_velocity = &ball.velocity
+                            - 2. * &ball.velocity.dot(normal) * normal * ball.surface.restitution;
                         ball.velocity = new_velocity;
                     }
+                    Event::BallKinematicCollision(ball, _, normal, wall_velocity) => {
+                        // bounce off the wall as seen from the wall, then add its velocity back
+                        let ball = &mut new.balls[*ball];
+                        let relative = &ball.velocity - wall_velocity;
+                        ball.velocity = wall_velocity + &relative
+                            - 2. * relative.dot(normal) * normal * ball.surface.restitution;
+                    }
+                    Event::ZoneCrossing(ball, edge) => {
+                        new.ignore_zone_crossings.push((*ball, *edge));
+                        let fixtures = Rc::clone(&new.fixtures);
+                        let ball = &mut new.balls[*ball];
+
+                        // the ball is right on the edge, look a bit ahead to see where it's going
+                        let ahead = &ball.position + ball.velocity.normalize() * 0.001;
+                        ball.surface = fixtures.surface_at(&ahead);
+                    }
+                    Event::Teleport(ball, teleporter) => {
+                        // whatever the ball was touching is left behind
+                        new.ignore_collisions
+                            .retain(|(a, b)| a != ball && b != ball);
+                        new.ignore_wall_collisions.retain(|(b, _)| b != ball);
+                        new.ignore_zone_crossings.retain(|(b, _)| b != ball);
+                        new.ignore_trigger_crossings.retain(|(b, _)| b != ball);
+
+                        let fixtures = Rc::clone(&new.fixtures);
+                        let teleporter = &fixtures.teleporters[*teleporter];
+                        let ball = &mut new.balls[*ball];
+
+                        ball.position = teleporter.exit;
+                        ball.velocity = teleporter.exit_velocity(&ball.velocity);
+                        ball.surface = fixtures.surface_at(&teleporter.exit);
+                    }
+                    Event::Trigger(ball, trigger, normal) => {
+                        new.ignore_trigger_crossings.push((*ball, *trigger));
+                        let fixtures = Rc::clone(&new.fixtures);
+                        let trigger = &fixtures.triggers[*trigger];
+                        new.gates_open[trigger.gate] =
+                            trigger.action.apply(new.gates_open[trigger.gate]);
+
+                        if let Some(normal) = normal {
+                            let ball = &mut new.balls[*ball];
+                            let new_velocity = &ball.velocity
+                                - 2. * &ball.velocity.dot(normal)
+                                    * normal
+                                    * ball.surface.restitution;
+                            ball.velocity = new_velocity;
+                        }
+                    }
+                    Event::Putt(_, _) => (),
                 }
             }
 
+            new.events = events;
+
             Some(new)
         } else {
             None
@@ -293,6 +810,7 @@ impl Snapshot<f64> {
                     ),
                 velocity: new_velocity,
                 acceleration: ball.acceleration.clone_owned(),
+                surface: ball.surface,
             });
         }
 
@@ -303,10 +821,32 @@ impl Snapshot<f64> {
             index: self.index + 1,
             ignore_collisions: Vec::new(),
             ignore_wall_collisions: Vec::new(),
+            ignore_zone_crossings: Vec::new(),
+            ignore_trigger_crossings: Vec::new(),
             walls: Rc::clone(&self.walls),
+            fixtures: Rc::clone(&self.fixtures),
+            gates_open: self.gates_open.clone(),
+            events: Vec::new(),
         }
     }
 
+    /// Ends this snapshot at `time`, and returns the snapshot starting from there with
+    /// `impulse` applied to `ball`
+    pub fn split_with_impulse(
+        &mut self,
+        time: Duration,
+        ball: usize,
+        impulse: Vector2<f64>,
+    ) -> Snapshot<f64> {
+        self.end_time = time;
+        let mut new = self.advance_to((time - self.start_time).as_secs_f64());
+        new.ignore_collisions = self.ignore_collisions.clone();
+        new.balls[ball].velocity = impulse;
+        new.events = vec![Event::Putt(ball, impulse)];
+
+        new
+    }
+
     fn handle_collision_pair(mut self, ball: usize, other: usize) -> Snapshot<f64> {
         let x1 = self.balls[ball].position.clone();
         let x2 = self.balls[other].position.clone();
@@ -317,8 +857,15 @@ impl Snapshot<f64> {
         let x1x2 = &x1 - &x2;
         let x2x1 = &x2 - &x1;
 
-        self.balls[ball].velocity = &v1 - v1v2.dot(&x1x2) / x1x2.norm_squared() * &x1x2 * 0.86;
-        self.balls[other].velocity = &v2 - v2v1.dot(&x2x1) / x2x1.norm_squared() * &x2x1 * 0.86;
+        // share of the impulse each ball takes, 1 for both when they weigh the same
+        let m1 = self.balls[ball].body.mass;
+        let m2 = self.balls[other].body.mass;
+        let k1 = 2. * m2 / (m1 + m2);
+        let k2 = 2. * m1 / (m1 + m2);
+
+        self.balls[ball].velocity = &v1 - v1v2.dot(&x1x2) / x1x2.norm_squared() * &x1x2 * k1 * 0.86;
+        self.balls[other].velocity =
+            &v2 - v2v1.dot(&x2x1) / x2x1.norm_squared() * &x2x1 * k2 * 0.86;
 
         self
     }
@@ -333,22 +880,22 @@ impl Snapshot<f64> {
 
             let vel_normalized = ball.velocity.normalize();
             let ff = Vector2::new(
-                0.50 * 9.81 * vel_normalized.x,
-                0.50 * 9.81 * vel_normalized.y,
+                0.50 * 9.81 * ball.surface.friction * vel_normalized.x,
+                0.50 * 9.81 * ball.surface.friction * vel_normalized.y,
             );
 
             ball.velocity.norm() / ff.norm()
         }
     }
 
-    fn ball_acceleration(ball: &PhysicsBody<Ball>) -> Vector2<f64> {
+    pub fn ball_acceleration(ball: &PhysicsBody<Ball>) -> Vector2<f64> {
         if ball.velocity.norm_squared() <= 1.0 {
             Vector2::new(0., 0.)
         } else {
             let vel_normalized = ball.velocity.normalize();
             let ff = Vector2::new(
-                0.50 * 9.81 * vel_normalized.x,
-                0.50 * 9.81 * vel_normalized.y,
+                0.50 * 9.81 * ball.surface.friction * vel_normalized.x,
+                0.50 * 9.81 * ball.surface.friction * vel_normalized.y,
             );
 
             ball.acceleration.clone_owned() - ff
@@ -356,43 +903,47 @@ impl Snapshot<f64> {
     }
 
     fn ball_line_toi(&self, ball: &PhysicsBody<Ball>, wall: &Wall) -> Option<f64> {
-        if wall.start.x == wall.end.x {
+        let acc = Self::ball_acceleration(ball);
+
+        let (a, b, c) = if wall.start.x == wall.end.x {
             // along y axis
-            None
+            (acc.x, ball.velocity.x, ball.position.x - wall.start.x)
         } else {
             let k = (&wall.end.y - &wall.start.y) / (&wall.end.x - &wall.start.x);
             let c = wall.start.y - wall.start.x * k;
-            let acc = Self::ball_acceleration(ball);
-            let a = acc.x * k - acc.y;
-            let b = k * ball.velocity.x - ball.velocity.y;
-            let c = k * ball.position.x - ball.position.y + c;
 
-            if a == 0. {
-                if b == 0. {
-                    None
-                } else {
-                    Some(c / b)
-                }
+            (
+                acc.x * k - acc.y,
+                k * ball.velocity.x - ball.velocity.y,
+                k * ball.position.x - ball.position.y + c,
+            )
+        };
+
+        if a == 0. {
+            if b == 0. {
+                None
             } else {
-                let d = b.powf(2.) - 2. * a * c;
+                Some(c / b)
+            }
+        } else {
+            let d = b.powf(2.) - 2. * a * c;
 
-                if d < 0. {
-                    None
-                } else {
-                    let t1 = (-b + d.sqrt()) / a;
-                    let t2 = (-b - d.sqrt()) / a;
+            if d < 0. {
+                None
+            } else {
+                let t1 = (-b + d.sqrt()) / a;
+                let t2 = (-b - d.sqrt()) / a;
 
-                    if t1 >= 0. {
-                        if t2 >= 0. && t2 < t1 {
-                            Some(t2)
-                        } else {
-                            Some(t1)
-                        }
-                    } else if t2 >= 0. {
+                if t1 >= 0. {
+                    if t2 >= 0. && t2 < t1 {
                         Some(t2)
                     } else {
-                        None
+                        Some(t1)
                     }
+                } else if t2 >= 0. {
+                    Some(t2)
+                } else {
+                    None
                 }
             }
         }
@@ -403,10 +954,11 @@ impl Snapshot<f64> {
             let acc = Self::ball_acceleration(ball);
             let impact_pos = &ball.position + &ball.velocity * toi + 0.5 * acc * toi.powf(2.);
 
-            let x1 = wall.start.x.min(wall.end.x);
-            let x2 = wall.start.x.max(wall.end.x);
-            let y1 = wall.start.y.min(wall.end.y);
-            let y2 = wall.start.y.max(wall.end.y);
+            // axis aligned walls have a zero width bounding box
+            let x1 = wall.start.x.min(wall.end.x) - WALL_MARGIN;
+            let x2 = wall.start.x.max(wall.end.x) + WALL_MARGIN;
+            let y1 = wall.start.y.min(wall.end.y) - WALL_MARGIN;
+            let y2 = wall.start.y.max(wall.end.y) + WALL_MARGIN;
 
             if impact_pos.x >= x1 && impact_pos.x <= x2 && impact_pos.y >= y1 && impact_pos.y <= y2
             {
@@ -419,6 +971,143 @@ impl Snapshot<f64> {
         }
     }
 
+    /// Time until the center of `ball` crosses a moving `wall`, looking at most `horizon` seconds
+    /// ahead, with the normal of the wall toward the ball and the velocity of the wall at the hit
+    ///
+    /// There is no closed form for this, so the gap between the ball and the wall is checked at
+    /// small steps and narrowed down where it changes sign.
+    fn ball_kinematic_toi(
+        &self,
+        ball: &PhysicsBody<Ball>,
+        wall: &KinematicWall,
+        horizon: f64,
+    ) -> Option<(f64, Vector2<f64>, Vector2<f64>)> {
+        let start = self.start_time.as_secs_f64();
+        let stop_t = self.ball_stop_time(ball);
+        // a ball at rest is hit within a period or never, a rolling one can be hit until it stops
+        let horizon = if ball.velocity.norm_squared() == 0. {
+            horizon.min(wall.motion.period())
+        } else {
+            horizon.min(stop_t)
+        };
+        let speed = ball.velocity.norm();
+        let (center, reach) = wall.bounds();
+
+        if !horizon.is_finite()
+            || (&ball.position - center).norm()
+                > reach + speed * horizon.min(stop_t) + KINEMATIC_STEP
+        {
+            return None;
+        }
+
+        let closing_speed = speed + wall.max_speed();
+
+        if closing_speed == 0. {
+            return None;
+        }
+
+        let acc = Self::ball_acceleration(ball);
+        let position = |t: f64| {
+            let t = t.min(stop_t);
+            &ball.position + &ball.velocity * t + 0.5 * &acc * t.powf(2.)
+        };
+        let gap = |t: f64| {
+            let w = wall.at(start + t);
+            let delta = &w.end - &w.start;
+            (position(t) - &w.start).dot(&Vector2::new(-delta.y, delta.x).normalize())
+        };
+
+        let step = KINEMATIC_STEP / closing_speed;
+        let mut previous = (0., gap(0.));
+
+        while previous.0 < horizon {
+            let t = (previous.0 + step).min(horizon);
+            let g = gap(t);
+
+            if previous.1 != 0. && g * previous.1 <= 0. {
+                let (mut low, mut high) = (previous.0, t);
+
+                for _ in 0..KINEMATIC_ITERATIONS {
+                    let mid = (low + high) / 2.;
+
+                    if gap(mid) * previous.1 > 0. {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+
+                // stop just short of the wall, so that the ball is still on its own side
+                let w = wall.at(start + low);
+                let p = position(low);
+                let delta = &w.end - &w.start;
+                let along = (&p - &w.start).dot(&delta) / delta.norm_squared();
+
+                if along >= -WALL_MARGIN && along <= 1. + WALL_MARGIN {
+                    let velocity = wall.motion.velocity(&p, start + low);
+                    let ball_velocity = if low < stop_t {
+                        &ball.velocity + &acc * low
+                    } else {
+                        Vector2::new(0., 0.)
+                    };
+
+                    // like static one way walls, but going by the ball's speed relative to the wall
+                    let through = w.one_way
+                        && (ball_velocity - &velocity).dot(&Vector2::new(delta.y, -delta.x)) >= 0.;
+
+                    if !through {
+                        let normal =
+                            Vector2::new(-delta.y, delta.x).normalize() * previous.1.signum();
+                        return Some((low, normal, velocity));
+                    }
+                }
+            }
+
+            previous = (t, g);
+        }
+
+        None
+    }
+
+    /// Time until the center of `ball` rolls onto the entry pad of `teleporter`
+    fn ball_teleporter_toi(ball: &PhysicsBody<Ball>, teleporter: &Teleporter) -> Option<f64> {
+        let to_ball = &ball.position - &teleporter.entry;
+
+        // balls already on the pad, like ones that just came out of it, stay put
+        if ball.velocity.norm_squared() == 0. || to_ball.norm() <= teleporter.radius {
+            return None;
+        }
+
+        // the ball rolls in a straight line, find how far along it the edge of the pad is
+        let speed = ball.velocity.norm();
+        let half_b = to_ball.dot(&ball.velocity) / speed;
+        let d = half_b.powf(2.) - to_ball.norm_squared() + teleporter.radius.powf(2.);
+
+        if d < 0. {
+            return None;
+        }
+
+        let distance = -half_b - d.sqrt();
+
+        if distance < 0. {
+            return None;
+        }
+
+        let deceleration = Self::ball_acceleration(ball).norm();
+
+        if deceleration == 0. {
+            return Some(distance / speed);
+        }
+
+        let end_speed = speed.powf(2.) - 2. * deceleration * distance;
+
+        if end_speed < 0. {
+            None
+        } else {
+            Some((speed - end_speed.sqrt()) / deceleration)
+        }
+    }
+
     fn ball_ball_toi(ball: &PhysicsBody<Ball>, other: &PhysicsBody<Ball>) -> Option<f64> {
         use num::Complex;
 
@@ -580,26 +1269,149 @@ impl Snapshot<f64> {
     }
 }
 
+/// Runs the solver until nothing happens anymore, appending the new snapshots
+pub fn simulate(snapshots: &mut Vec<Snapshot<f64>>) {
+    while let Some(next) = snapshots.last_mut().and_then(|s| s.next_snapshot()) {
+        snapshots.push(next);
+    }
+}
+
+/// Applies `impulse` to `ball` at `time`, throwing away and re-simulating everything after it.
+///
+/// Returns false if there is no snapshot covering `time`.
+pub fn insert_impulse(
+    snapshots: &mut Vec<Snapshot<f64>>,
+    time: Duration,
+    ball: usize,
+    impulse: Vector2<f64>,
+) -> bool {
+    let index = match snapshots.iter().position(|s| s.end_time >= time) {
+        Some(index) => index,
+        None => return false,
+    };
+
+    snapshots.truncate(index + 1);
+    let new = snapshots[index].split_with_impulse(time, ball, impulse);
+    snapshots.push(new);
+    simulate(snapshots);
+
+    true
+}
+
+/// Takes back the latest putt in the timeline, re-simulating everything after it.
+///
+/// Returns the time the putt was made at.
+pub fn remove_last_impulse(snapshots: &mut Vec<Snapshot<f64>>) -> Option<Duration> {
+    let index = snapshots.iter().rposition(|s| {
+        s.events.iter().any(|e| match e {
+            Event::Putt(_, _) => true,
+            _ => false,
+        })
+    })?;
+
+    let time = snapshots[index].start_time;
+    snapshots.truncate(index);
+
+    // the snapshot was cut short by the putt, let the solver figure out where it really ends
+    snapshots[index - 1].end_time = Duration::new(u64::MAX, 999_999_999);
+    simulate(snapshots);
+
+    Some(time)
+}
+
+/// Maximum amount of snapshots calculated for a single trajectory prediction
+const PREDICTION_SNAPSHOT_LIMIT: usize = 64;
+
+/// Predicts where `ball` goes if `impulse` is applied to it at `time`, using a scratch copy of
+/// the timeline. Stops after `max_collisions` collisions involving the ball.
+pub fn predict_trajectory(
+    snapshot: &Snapshot<f64>,
+    time: Duration,
+    ball: usize,
+    impulse: Vector2<f64>,
+    max_collisions: usize,
+) -> Trajectory {
+    let mut scratch = snapshot.clone();
+    let mut current = scratch.split_with_impulse(time, ball, impulse);
+    let mut trajectory = Trajectory {
+        points: vec![current.balls[ball].position.clone()],
+        collisions: Vec::new(),
+    };
+
+    for _ in 0..PREDICTION_SNAPSHOT_LIMIT {
+        if current.balls[ball].velocity.norm_squared() == 0. {
+            break;
+        }
+
+        let next = match current.next_snapshot() {
+            Some(next) => next,
+            None => break,
+        };
+
+        // the line would jump over to the exit, end it on the pad instead
+        let teleported = next.events.iter().any(|e| match e {
+            Event::Teleport(b, _) => *b == ball,
+            _ => false,
+        });
+
+        if teleported {
+            trajectory
+                .points
+                .push(current.ball_pos(ball, next.start_time).0);
+            break;
+        }
+
+        current = next;
+
+        let position = current.balls[ball].position.clone();
+        trajectory.points.push(position.clone());
+
+        if current.events.iter().any(|e| e.is_collision_of(ball)) {
+            trajectory.collisions.push(position);
+
+            if trajectory.collisions.len() >= max_collisions {
+                break;
+            }
+        }
+    }
+
+    trajectory
+}
+
 pub struct PhysicsAnimator<N: RealField> {
     snapshots: Rc<RefCell<Vec<Snapshot<N>>>>,
     timer: Rc<RefCell<Duration>>,
     paused: bool,
+    loop_after: Option<Duration>,
+    /// Drives the timer instead of the frame times when set
+    clock: Option<Rc<RefCell<GameClock>>>,
 }
 
 impl<N> PhysicsAnimator<N>
 where
     N: RealField,
 {
+    /// `loop_after` rewinds the timer back to zero once it has been reached
     pub fn new(
         snapshots: Rc<RefCell<Vec<Snapshot<N>>>>,
         timer: Rc<RefCell<Duration>>,
+        loop_after: Option<Duration>,
     ) -> PhysicsAnimator<N> {
         PhysicsAnimator {
             snapshots,
             timer,
             paused: false,
+            loop_after,
+            clock: None,
         }
     }
+
+    /// Follows `clock` instead of counting frame times. The timer stops at `loop_after`
+    /// rather than rewinding, so the end of the game stays on screen.
+    pub fn with_clock(mut self, clock: Rc<RefCell<GameClock>>) -> PhysicsAnimator<N> {
+        self.clock = Some(clock);
+        self
+    }
 }
 
 impl<W> System<W> for PhysicsAnimator<f64>
@@ -632,11 +1444,16 @@ where
 
         let mut current_time = self.timer.borrow_mut();
 
-        if !self.paused {
+        if let (false, Some(clock)) = (self.paused, &self.clock) {
+            let time = (**clock).borrow().time(Instant::now());
+            *current_time = self.loop_after.map_or(time, |end| time.min(end));
+        } else if !self.paused {
             *current_time += delta;
 
-            if *current_time >= Duration::new(30, 0) {
-                *current_time = Duration::new(0, 0);
+            if let Some(loop_after) = self.loop_after {
+                if *current_time >= loop_after {
+                    *current_time = Duration::new(0, 0);
+                }
             }
         }
 
@@ -703,3 +1520,59 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(x: f64, velocity: Vector2<f64>) -> PhysicsBody<Ball> {
+        PhysicsBody {
+            body: Ball {
+                radius: 5.,
+                mass: 1.,
+            },
+            position: Point2::new(x, 0.),
+            velocity,
+            acceleration: Vector2::new(0., 0.),
+            surface: Surface::default(),
+        }
+    }
+
+    fn vertical_wall(x: f64) -> Wall {
+        Wall {
+            start: Point2::new(x, -500.),
+            end: Point2::new(x, 500.),
+            one_way: false,
+        }
+    }
+
+    fn run(balls: Vec<PhysicsBody<Ball>>, walls: Vec<Wall>) -> Vec<Snapshot<f64>> {
+        let mut snapshots = vec![Snapshot::new(balls, Rc::new(RefCell::new(walls)))];
+        simulate(&mut snapshots);
+        snapshots
+    }
+
+    #[test]
+    fn ball_bounces_off_vertical_wall() {
+        let snapshots = run(
+            vec![ball(150., Vector2::new(300., 0.))],
+            vec![vertical_wall(200.)],
+        );
+
+        assert!(snapshots.iter().any(|s| s
+            .events
+            .iter()
+            .any(|e| matches!(e, Event::BallStaticCollision(0, 0, _)))));
+        assert!(snapshots.last().unwrap().balls[0].position.x < 200.);
+    }
+    #[test]
+    fn ball_stays_between_parallel_walls() {
+        let snapshots = run(
+            vec![ball(150., Vector2::new(300., 13.))],
+            vec![vertical_wall(0.), vertical_wall(200.)],
+        );
+
+        let x = snapshots.last().unwrap().balls[0].position.x;
+        assert!(x > 0. && x < 200.);
+    }
+}
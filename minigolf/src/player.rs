@@ -1,6 +1,11 @@
-use crate::api::{Player, PublicPlay, PublicStates, Putt};
-use crate::physics::{BallComponent, Snapshot, Wall};
+use crate::net::{ApiError, NetWorker, Pending};
+use crate::physics::{
+    insert_impulse, predict_trajectory, simulate, BallComponent, Snapshot, Trajectory, Wall, Zone,
+};
+use crate::score::HoleProgress;
+use crate::shot::{indicator_color, indicator_shape, Shot, ShotGesture};
 use crate::world::MyWorld;
+use api::{Player, PublicPlay, PublicPut, PublicStates, Putt, MAX_PUTT_IMPULSE, PLAY_TIME};
 use mela::debug::DebugContext;
 use mela::ecs::component::Transform;
 use mela::ecs::system::{Read, Write};
@@ -12,7 +17,6 @@ use mela::lyon::lyon_algorithms::path::Path;
 use mela::lyon::lyon_tessellation::math::Point;
 use mela::nalgebra as na;
 use mela::nalgebra::Vector2;
-use reqwest::blocking::Client;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
@@ -26,14 +30,29 @@ impl Component for PlayerController {}
 pub struct PlayerInput {
     timer: Rc<RefCell<Duration>>,
     snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+    preview_collisions: Option<usize>,
+    show_preview: bool,
+    gesture: ShotGesture,
+    progress: Rc<RefCell<HoleProgress>>,
 }
 
 impl PlayerInput {
+    /// `preview_collisions` enables the trajectory preview, showing the predicted path up to
+    /// that many collisions
     pub fn new(
         timer: Rc<RefCell<Duration>>,
         snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+        progress: Rc<RefCell<HoleProgress>>,
+        preview_collisions: Option<usize>,
     ) -> PlayerInput {
-        PlayerInput { timer, snapshots }
+        PlayerInput {
+            timer,
+            snapshots,
+            preview_collisions,
+            show_preview: preview_collisions.is_some(),
+            gesture: ShotGesture::new(),
+            progress,
+        }
     }
 }
 
@@ -42,12 +61,45 @@ pub struct HitIndicator {}
 
 impl Component for HitIndicator {}
 
+/// Marks the primitive used to draw the predicted trajectory
+#[derive(Debug)]
+pub struct TrajectoryIndicator {}
+
+impl Component for TrajectoryIndicator {}
+
+fn trajectory_path(trajectory: &Trajectory) -> Path {
+    let marker_size = 4.;
+    let mut path = Path::builder();
+
+    for (i, point) in trajectory.points.iter().enumerate() {
+        let point = Point::new(point.x as f32, point.y as f32);
+
+        if i == 0 {
+            path.move_to(point);
+        } else {
+            path.line_to(point);
+        }
+    }
+
+    for collision in &trajectory.collisions {
+        let (x, y) = (collision.x as f32, collision.y as f32);
+
+        path.move_to(Point::new(x - marker_size, y - marker_size));
+        path.line_to(Point::new(x + marker_size, y + marker_size));
+        path.move_to(Point::new(x - marker_size, y + marker_size));
+        path.line_to(Point::new(x + marker_size, y - marker_size));
+    }
+
+    path.build()
+}
+
 impl System<MyWorld> for PlayerInput {
     type SystemData<'a> = (
         Read<'a, PlayerController>,
         Read<'a, BallComponent>,
         Read<'a, Transform<f64>>,
         Read<'a, HitIndicator>,
+        Read<'a, TrajectoryIndicator>,
         Write<'a, PrimitiveComponent>,
     );
 
@@ -57,7 +109,7 @@ impl System<MyWorld> for PlayerInput {
 
     fn update<'f>(
         &mut self,
-        (controller, balls, transforms, indicators, mut primitives): Self::SystemData<'f>,
+        (controller, balls, transforms, indicators, trajectory_indicators, mut primitives): Self::SystemData<'f>,
         delta: Duration,
         io_state: &IoState,
         render_ctx: &mut RenderContext,
@@ -69,10 +121,19 @@ impl System<MyWorld> for PlayerInput {
         let ball = balls.fetch(entity).unwrap();
         let transform = transforms.fetch(entity).unwrap().clone();
 
-        let impulse = Vector2::new(
+        let ball_position = transform.0.translation.vector.clone_owned();
+        let mouse_position = Vector2::new(
             io_state.mouse_position[0] as f64,
             io_state.mouse_position[1] as f64,
-        ) - &transform.0.translation.vector;
+        );
+
+        let shot = if self.progress.borrow().completed {
+            self.gesture.reset();
+            Shot::Idle
+        } else {
+            self.gesture
+                .update(io_state.mouse_buttons[0], &ball_position, &mouse_position)
+        };
 
         let (indicator_entity, _) = indicators.iter().next().unwrap();
         let (_, indicator_prim) = primitives
@@ -80,71 +141,107 @@ impl System<MyWorld> for PlayerInput {
             .find(|(e, _)| *e == indicator_entity)
             .unwrap();
 
-        let start_point_vec = &transform.0.translation.vector + impulse.normalize() * 30.;
-        let start_point = Point::new(start_point_vec.x as f32, start_point_vec.y as f32);
-        let end_point = Point::new(io_state.mouse_position[0], io_state.mouse_position[1]);
-
-        let mut path = Path::builder();
-        path.move_to(start_point);
-        path.line_to(end_point);
-
-        indicator_prim.shape = PrimitiveShape::Path(path.build());
-
-        if io_state.mouse_buttons[0] {
-            if let Some(snapshot_index) = {
-                let snapshots = (*self.snapshots).borrow();
-                let mut found = None;
-                for (i, snapshot) in snapshots.iter().enumerate() {
-                    if snapshot.end_time >= *current_time {
-                        found = Some(i);
-                        break;
-                    }
+        let aim = match shot {
+            Shot::Aiming { impulse, power } => {
+                indicator_prim.color = indicator_color(power);
+                indicator_prim.shape = indicator_shape(&ball_position, &impulse, power);
+                Some(impulse)
+            }
+            _ => {
+                indicator_prim.shape = PrimitiveShape::Path(Path::new());
+                None
+            }
+        };
+
+        if let Some(max_collisions) = self.preview_collisions {
+            use mela::imgui::im_str;
+            debug_ctx
+                .ui
+                .checkbox(im_str!("Trajectory preview"), &mut self.show_preview);
+
+            let trajectory = match aim {
+                Some(impulse) if self.show_preview => {
+                    let snapshots = self.snapshots.borrow();
+
+                    snapshots
+                        .iter()
+                        .find(|s| s.end_time >= *current_time)
+                        .map(|snapshot| {
+                            predict_trajectory(
+                                snapshot,
+                                *current_time,
+                                ball.index,
+                                impulse,
+                                max_collisions,
+                            )
+                        })
+                        .unwrap_or_default()
                 }
+                _ => Trajectory::default(),
+            };
 
-                found
-            } {
-                let start_time = Instant::now();
-                let mut snapshots = self.snapshots.borrow_mut();
-                let mut current_snapshot = &mut snapshots[snapshot_index];
-                current_snapshot.end_time = current_time.clone();
-                let mut new = current_snapshot
-                    .advance_to((*current_time - current_snapshot.start_time).as_secs_f64());
-                new.ignore_collisions = current_snapshot.ignore_collisions.clone();
-
-                new.balls[ball.index].velocity = impulse;
-
-                let mut new_snapshots: Vec<Snapshot<f64>> =
-                    snapshots[..=snapshot_index].iter().cloned().collect();
-                new_snapshots.push(new);
+            if let Some((trajectory_entity, _)) = trajectory_indicators.iter().next() {
+                let (_, trajectory_prim) = primitives
+                    .iter_mut()
+                    .find(|(e, _)| *e == trajectory_entity)
+                    .unwrap();
 
-                let mut seed_index = snapshot_index + 1;
+                trajectory_prim.shape = PrimitiveShape::Path(trajectory_path(&trajectory));
+            }
+        }
 
-                while let Some(next) = new_snapshots[seed_index].next_snapshot() {
-                    new_snapshots.push(next);
-                    seed_index += 1;
-                }
+        if let Shot::Fire(impulse) = shot {
+            let start_time = Instant::now();
+            let mut snapshots = self.snapshots.borrow_mut();
 
+            if insert_impulse(&mut snapshots, *current_time, ball.index, impulse) {
                 println!("physics calculations took {:?}", start_time.elapsed());
-
-                *snapshots = new_snapshots;
             }
         }
     }
 }
 
+/// Sand is drawn yellow and ice light blue, anything else a dull green
+pub fn zone_color(zone: &Zone) -> [f32; 4] {
+    if zone.surface.friction > 1. {
+        [0.9, 0.8, 0.4, 1.]
+    } else if zone.surface.friction < 1. {
+        [0.6, 0.9, 1., 1.]
+    } else {
+        [0.2, 0.6, 0.2, 1.]
+    }
+}
+
+/// Outline of a zone
+pub fn zone_shape(zone: &Zone) -> PrimitiveShape {
+    let mut path = Path::builder();
+
+    for edge in zone.edges() {
+        path.move_to(Point::new(edge.start.x as f32, edge.start.y as f32));
+        path.line_to(Point::new(edge.end.x as f32, edge.end.y as f32));
+    }
+
+    PrimitiveShape::Path(path.build())
+}
+
 #[derive(Debug)]
 pub struct WallComponent {}
 
 impl Component for WallComponent {}
 
 pub struct LineDrawer {
+    timer: Rc<RefCell<Duration>>,
     snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
     added_wall: bool,
 }
 
 impl LineDrawer {
-    pub fn new(snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>) -> LineDrawer {
+    pub fn new(
+        timer: Rc<RefCell<Duration>>,
+        snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+    ) -> LineDrawer {
         LineDrawer {
+            timer,
             snapshots,
             added_wall: false,
         }
@@ -167,7 +264,9 @@ impl System<MyWorld> for LineDrawer {
         debug_ctx: &mut DebugContext,
     ) -> () {
         let snapshots = self.snapshots.borrow_mut();
-        let mut walls = snapshots.first().unwrap().walls.borrow_mut();
+        let first = snapshots.first().unwrap();
+        let mut walls = first.walls.borrow_mut();
+        let time = self.timer.borrow().as_secs_f64();
 
         let (wall_entity, _) = wall_components.iter().next().unwrap();
         let (_, primitive) = primitives
@@ -185,40 +284,297 @@ impl System<MyWorld> for LineDrawer {
             path_builder.line_to(Point::new(ex as f32, ey as f32));
         }
 
+        // gates are drawn the way they are in the snapshot being played
+        let current = snapshots
+            .iter()
+            .rev()
+            .find(|s| s.start_time.as_secs_f64() <= time)
+            .unwrap_or(first);
+        let fixtures = &first.fixtures;
+
+        let moving = fixtures.kinematic_walls.iter().map(|w| w.at(time));
+        let gates = fixtures
+            .gates
+            .iter()
+            .zip(&current.gates_open)
+            .filter(|(_, open)| !**open)
+            .flat_map(|(gate, _)| gate.walls.iter().cloned());
+        let switches = fixtures
+            .triggers
+            .iter()
+            .filter(|t| t.switch)
+            .map(|t| t.line.clone());
+
+        for wall in moving.chain(gates).chain(switches) {
+            path_builder.move_to(Point::new(wall.start.x as f32, wall.start.y as f32));
+            path_builder.line_to(Point::new(wall.end.x as f32, wall.end.y as f32));
+        }
+
         primitive.shape = PrimitiveShape::Path(path_builder.build());
     }
 }
 
+/// What became of the player's last putt in a multiplayer game
+#[derive(Clone, Debug)]
+pub enum PuttStatus {
+    Idle,
+    Sending,
+    Failed(ApiError),
+    /// The putt failed and the timeline couldn't be rebuilt without it either, so what's
+    /// shown may be off until the next update from the server
+    RollbackFailed {
+        putt: ApiError,
+        rollback: ApiError,
+    },
+}
+
+/// Takes the predicted putt back out of the timeline after it failed with `error`
+fn take_back(
+    puts: &mut PuttLog,
+    snapshots: &mut Vec<Snapshot<f64>>,
+    error: ApiError,
+) -> PuttStatus {
+    match puts.rollback(snapshots) {
+        Ok(()) => PuttStatus::Failed(error),
+        Err(rollback) => PuttStatus::RollbackFailed {
+            putt: error,
+            rollback,
+        },
+    }
+}
+
+/// Own putt that's played in the timeline before the server has it
+#[derive(Clone, Debug)]
+struct Prediction {
+    ball: usize,
+    time: Duration,
+    impulse: [f64; 2],
+}
+
+impl Prediction {
+    /// Is `putt` the server's record of this one. The server may have moved it in time, but
+    /// the ball and the impulse are what was sent, give or take the trip through JSON.
+    fn matches(&self, putt: &PublicPut) -> bool {
+        let [x, y] = self.impulse;
+        let [px, py] = putt.impulse;
+
+        putt.player.id == self.ball && (x - px).abs() < 1e-6 && (y - py).abs() < 1e-6
+    }
+}
+
+/// Putts the server has sent so far, and the own putt it hasn't confirmed yet. The putt ids
+/// are sequence numbers, so the count doubles as the cursor for asking only for newer ones.
+#[derive(Debug, Default)]
+pub struct PuttLog {
+    puts: Vec<PublicPut>,
+    predicted: Option<Prediction>,
+}
+
+impl PuttLog {
+    /// Number of putts received, which is the cursor for asking for newer ones
+    pub fn cursor(&self) -> usize {
+        self.puts.len()
+    }
+
+    /// Is there an own putt in the timeline the server hasn't confirmed
+    pub fn is_predicting(&self) -> bool {
+        self.predicted.is_some()
+    }
+
+    /// Plays an own putt right away, before the server has seen it. It stays in the timeline
+    /// until the server's list has it, or it's rolled back.
+    pub fn predict(
+        &mut self,
+        snapshots: &mut Vec<Snapshot<f64>>,
+        ball: usize,
+        time: Duration,
+        impulse: [f64; 2],
+    ) -> Result<(), ApiError> {
+        self.predicted = Some(Prediction {
+            ball,
+            time,
+            impulse,
+        });
+
+        self.replay(snapshots, time)
+    }
+
+    /// Takes the predicted putt back out of the timeline, when the server refused it
+    pub fn rollback(&mut self, snapshots: &mut Vec<Snapshot<f64>>) -> Result<(), ApiError> {
+        match self.predicted.take() {
+            Some(predicted) => self.replay(snapshots, predicted.time),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds the putts not seen before to the log and the timeline. Only the part of the
+    /// timeline from the earliest new putt on is re-simulated. The putts come from other
+    /// clients, so anything that would break the replay is rejected. A new putt that is the
+    /// predicted one confirms it, the timeline only changes if the server moved it.
+    pub fn splice(
+        &mut self,
+        snapshots: &mut Vec<Snapshot<f64>>,
+        mut puts: Vec<PublicPut>,
+    ) -> Result<(), ApiError> {
+        puts.retain(|p| p.id >= self.puts.len());
+        puts.sort_by_key(|p| p.id);
+
+        let balls = snapshots[0].balls.len();
+
+        for (i, putt) in puts.iter().enumerate() {
+            let [x, y] = putt.impulse;
+
+            if putt.id != self.puts.len() + i {
+                return Err(ApiError::Protocol(format!(
+                    "expected putt {}, got {}",
+                    self.puts.len() + i,
+                    putt.id
+                )));
+            }
+
+            if putt.player.id >= balls {
+                return Err(ApiError::Protocol(format!(
+                    "putt {} is for ball {}, there are only {}",
+                    putt.id, putt.player.id, balls
+                )));
+            }
+
+            if putt.time > PLAY_TIME {
+                return Err(ApiError::Protocol(format!(
+                    "putt {} is after the end of the game",
+                    putt.id
+                )));
+            }
+
+            if !(x.is_finite() && y.is_finite()) || x.hypot(y) > MAX_PUTT_IMPULSE + 1e-6 {
+                return Err(ApiError::Protocol(format!(
+                    "putt {} has an impulse over the limit",
+                    putt.id
+                )));
+            }
+        }
+
+        let mut changed: Vec<Duration> = Vec::new();
+
+        let confirmed = self
+            .predicted
+            .as_ref()
+            .and_then(|predicted| puts.iter().find(|p| predicted.matches(p)))
+            .map(|putt| putt.id);
+
+        for putt in &puts {
+            if Some(putt.id) != confirmed {
+                changed.push(putt.time);
+            }
+        }
+
+        if let Some(id) = confirmed {
+            let predicted = self.predicted.take().unwrap();
+            let putt = puts.iter().find(|p| p.id == id).unwrap();
+
+            // already played at the right time, it's just no longer a prediction
+            if putt.time != predicted.time {
+                changed.push(predicted.time);
+                changed.push(putt.time);
+            }
+        }
+
+        self.puts.extend(puts);
+
+        match changed.into_iter().min() {
+            Some(earliest) => self.replay(snapshots, earliest),
+            None => Ok(()),
+        }
+    }
+
+    /// Re-simulates the timeline from `from` on with every putt at or after it, including
+    /// the predicted one
+    fn replay(&self, snapshots: &mut Vec<Snapshot<f64>>, from: Duration) -> Result<(), ApiError> {
+        let keep = snapshots
+            .iter()
+            .rposition(|s| s.start_time < from)
+            .unwrap_or(0);
+        snapshots.truncate(keep + 1);
+        snapshots[keep].end_time = Duration::new(u64::MAX, 999_999_999);
+
+        // the prediction goes after the server's putts at the same time, like it would once
+        // the server has given it the next id
+        let confirmed = self
+            .puts
+            .iter()
+            .map(|p| (p.time, p.id, p.player.id, p.impulse));
+        let predicted = self
+            .predicted
+            .iter()
+            .map(|p| (p.time, usize::MAX, p.ball, p.impulse));
+
+        let mut replayed: Vec<_> = confirmed
+            .chain(predicted)
+            .filter(|(time, ..)| *time >= from)
+            .collect();
+        replayed.sort_by_key(|(time, id, ..)| (*time, *id));
+
+        // the timeline has to run up to the first putt, unless the replay starts at one
+        if replayed.first().map_or(true, |(time, ..)| *time > from) {
+            simulate(snapshots);
+        }
+
+        for (time, _, ball, impulse) in replayed {
+            let i = snapshots
+                .iter()
+                .position(|s| s.end_time > time)
+                .ok_or_else(|| {
+                    ApiError::Protocol(format!(
+                        "putt at {:?} is past the end of the timeline",
+                        time
+                    ))
+                })?;
+
+            snapshots.truncate(i + 1);
+            let new = snapshots[i].split_with_impulse(time, ball, impulse.into());
+            snapshots.push(new);
+            simulate(snapshots);
+        }
+
+        Ok(())
+    }
+}
+
 pub struct MultiplayerInput {
     timer: Rc<RefCell<Duration>>,
     snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
-    initial_snapshot: Snapshot<f64>,
+    puts: Rc<RefCell<PuttLog>>,
     click_cooldown: Duration,
-    client: Rc<Client>,
-    endpoint: String,
+    net: NetWorker,
     game_id: usize,
     uuid: Uuid,
+    gesture: ShotGesture,
+    /// Putt on its way to the server
+    putt: Option<Pending<PublicStates>>,
+    putt_status: Rc<RefCell<PuttStatus>>,
 }
 
 impl MultiplayerInput {
     pub fn new(
         timer: Rc<RefCell<Duration>>,
         snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
-        initial_snapshot: Snapshot<f64>,
-        client: Rc<Client>,
-        endpoint: String,
+        puts: Rc<RefCell<PuttLog>>,
+        net: NetWorker,
         game_id: usize,
         uuid: Uuid,
+        putt_status: Rc<RefCell<PuttStatus>>,
     ) -> MultiplayerInput {
         MultiplayerInput {
             timer,
             snapshots,
-            initial_snapshot,
-            client,
-            endpoint,
+            puts,
+            net,
             game_id,
             click_cooldown: Duration::new(0, 0),
             uuid,
+            gesture: ShotGesture::new(),
+            putt: None,
+            putt_status,
         }
     }
 }
@@ -247,97 +603,106 @@ impl System<MyWorld> for MultiplayerInput {
         let (entity, _) = controller.iter().next().unwrap();
         let current_time = self.timer.borrow();
 
+        if let Some(result) = self.putt.as_ref().and_then(Pending::poll) {
+            self.putt = None;
+
+            let mut puts = self.puts.borrow_mut();
+            let mut snapshots = self.snapshots.borrow_mut();
+
+            let spliced = result.and_then(|state| match state {
+                PublicStates::Play(state) => puts.splice(&mut snapshots, state.puts),
+                _ => Err(ApiError::Protocol(
+                    "putt was answered with a game that isn't on".to_owned(),
+                )),
+            });
+
+            // the answer lists every putt, so a prediction still standing wasn't taken
+            let confirmed = spliced.and_then(|()| {
+                if puts.is_predicting() {
+                    Err(ApiError::Protocol(
+                        "putt was accepted but isn't in the game".to_owned(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            });
+
+            *self.putt_status.borrow_mut() = match confirmed {
+                Ok(()) => PuttStatus::Idle,
+                Err(e) => take_back(&mut puts, &mut snapshots, e),
+            };
+        }
+
         let ball = balls.fetch(entity).unwrap();
         let transform = transforms.fetch(entity).unwrap().clone();
 
         self.click_cooldown += delta;
 
+        // the ball is faded while it's playing a putt the server hasn't confirmed
+        if let Some((_, ball_prim)) = primitives.iter_mut().find(|(e, _)| *e == entity) {
+            ball_prim.color[3] = if self.puts.borrow().is_predicting() {
+                0.5
+            } else {
+                1.
+            };
+        }
+
         let (indicator_entity, _) = indicators.iter().next().unwrap();
         let (_, indicator_prim) = primitives
             .iter_mut()
             .find(|(e, _)| *e == indicator_entity)
             .unwrap();
 
-        if self.click_cooldown >= Duration::new(1, 0) {
-            let impulse = Vector2::new(
+        // only one putt is predicted at a time
+        if self.click_cooldown >= Duration::new(1, 0) && self.putt.is_none() {
+            let ball_position = transform.0.translation.vector.clone_owned();
+            let mouse_position = Vector2::new(
                 io_state.mouse_position[0] as f64,
                 io_state.mouse_position[1] as f64,
-            ) - &transform.0.translation.vector;
+            );
 
-            let start_point_vec = &transform.0.translation.vector + impulse.normalize() * 30.;
-            let start_point = Point::new(start_point_vec.x as f32, start_point_vec.y as f32);
-            let end_point = Point::new(io_state.mouse_position[0], io_state.mouse_position[1]);
+            let shot =
+                self.gesture
+                    .update(io_state.mouse_buttons[0], &ball_position, &mouse_position);
 
-            let mut path = Path::builder();
-            path.move_to(start_point);
-            path.line_to(end_point);
-
-            indicator_prim.shape = PrimitiveShape::Path(path.build());
+            match shot {
+                Shot::Aiming { impulse, power } => {
+                    indicator_prim.color = indicator_color(power);
+                    indicator_prim.shape = indicator_shape(&ball_position, &impulse, power);
+                }
+                _ => indicator_prim.shape = PrimitiveShape::Path(Path::new()),
+            }
 
-            if io_state.mouse_buttons[0] {
+            if let Shot::Fire(impulse) = shot {
                 self.click_cooldown = Duration::new(0, 0);
 
                 let time = self.timer.borrow().clone();
+                let impulse: [f64; 2] = impulse.into();
 
-                let resp = self
-                    .client
-                    .put(&self.endpoint)
-                    .json::<Putt>(&Putt {
-                        id: self.game_id,
-                        player: Player {
-                            uuid: self.uuid.to_hyphenated().to_string(),
-                        },
-                        time,
-                        impulse: impulse.into(),
-                    })
-                    .send()
-                    .unwrap()
-                    .json::<PublicStates>();
-
-                match resp {
-                    Ok(PublicStates::Play(mut state)) => {
-                        let mut snapshots = vec![self.initial_snapshot.clone()];
-
-                        state.puts.sort_by(|a, b| a.time.cmp(&b.time));
-
-                        for putt in state.puts {
-                            let (i, snapshot) = snapshots
-                                .iter_mut()
-                                .enumerate()
-                                .find(|(_, s)| s.end_time > putt.time)
-                                .unwrap();
-
-                            snapshot.end_time = putt.time.clone();
-                            let mut new = snapshot
-                                .advance_to((putt.time - snapshot.start_time).as_secs_f64());
-
-                            new.ignore_collisions = snapshot.ignore_collisions.clone();
-
-                            new.balls[putt.player.id].velocity = putt.impulse.into();
-
-                            let mut new_snapshots: Vec<Snapshot<f64>> =
-                                snapshots[..=i].iter().cloned().collect();
-                            new_snapshots.push(new);
-
-                            let mut seed_index = i + 1;
-
-                            while let Some(next) = new_snapshots[seed_index].next_snapshot() {
-                                new_snapshots.push(next);
-                                seed_index += 1;
-                            }
-
-                            snapshots = new_snapshots;
-                        }
+                let mut puts = self.puts.borrow_mut();
+                let mut snapshots = self.snapshots.borrow_mut();
 
-                        {
-                            let mut lock = self.snapshots.borrow_mut();
-                            *lock = snapshots;
+                // a putt that can't be played here isn't sent either
+                *self.putt_status.borrow_mut() =
+                    match puts.predict(&mut snapshots, ball.index, time, impulse) {
+                        Ok(()) => {
+                            self.putt = Some(self.net.putt(Putt {
+                                id: self.game_id,
+                                player: Player {
+                                    uuid: self.uuid.to_hyphenated().to_string(),
+                                },
+                                ball: Some(ball.index),
+                                time,
+                                impulse,
+                            }));
+
+                            PuttStatus::Sending
                         }
-                    }
-                    _ => (),
-                }
+                        Err(e) => take_back(&mut puts, &mut snapshots, e),
+                    };
             }
         } else {
+            self.gesture.reset();
             indicator_prim.shape = PrimitiveShape::Path(Path::new())
         }
     }
@@ -1 +1,77 @@
 //! Resources are data that is not tied to any entity
+
+use crate::course::{Course, CourseError};
+use crate::net::NetWorker;
+use crate::settings::Settings;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const DEFAULT_COURSE_PATH: &str = "courses/default.json";
+pub const ARENA_COURSE_PATH: &str = "courses/arena.json";
+/// Where the courses a lobby host can choose from are, they are picked by file name
+pub const COURSE_DIR: &str = "courses";
+
+pub struct Resources {
+    /// Course played in single player
+    pub course: Rc<Course>,
+    /// Where `course` was loaded from
+    pub course_path: PathBuf,
+    /// Multiplayer layout, only the first hole is used
+    pub arena: Rc<Course>,
+    pub settings: Settings,
+    /// Lobby server connection, requests go to `settings.endpoint`
+    pub net: NetWorker,
+}
+
+impl Resources {
+    /// `course` can be a course file, a Tiled map or an SVG drawing
+    pub fn load(course: &Path, settings: Settings) -> Result<Resources, CourseError> {
+        Ok(Resources {
+            course: Rc::new(Course::load(course)?),
+            course_path: course.to_owned(),
+            arena: Rc::new(Course::load(ARENA_COURSE_PATH)?),
+            net: NetWorker::new(settings.endpoint.clone()),
+            settings,
+        })
+    }
+
+    /// Course of a lobby, the arena when the host hasn't chosen one. The name comes from the
+    /// server, so anything but a file name in `COURSE_DIR` is refused.
+    pub fn lobby_course(&self, name: Option<&str>) -> Result<Rc<Course>, CourseError> {
+        match name {
+            Some(name) if !api::is_course_file_name(name) => Err(CourseError::Invalid {
+                path: PathBuf::from(name),
+                hole: None,
+                message: "not a course file name".to_owned(),
+            }),
+            Some(name) => Ok(Rc::new(Course::load(Path::new(COURSE_DIR).join(name))?)),
+            None => Ok(Rc::clone(&self.arena)),
+        }
+    }
+}
+
+/// Courses in `COURSE_DIR` a lobby can play, by file name along with how many balls the
+/// first hole has. Each player needs a ball, so courses with just the tee are left out, as
+/// are files that don't load.
+pub fn lobby_courses() -> Vec<(String, usize)> {
+    let entries = match fs::read_dir(COURSE_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut courses: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let course = Course::load(entry.path()).ok()?;
+            let balls = 1 + course.holes.first()?.rack.len();
+
+            Some((name, balls))
+        })
+        .filter(|(_, balls)| *balls > 1)
+        .collect();
+
+    courses.sort();
+    courses
+}
@@ -0,0 +1,264 @@
+//! Stroke counting and hole completion, derived from the snapshot timeline
+
+use crate::course::{Course, Hole};
+use crate::physics::{BallComponent, Event, Snapshot};
+use crate::player::PlayerController;
+use crate::world::MyWorld;
+use mela::debug::DebugContext;
+use mela::ecs::component::Transform;
+use mela::ecs::system::{Read, Write};
+use mela::ecs::System;
+use mela::game::IoState;
+use mela::gfx::primitives::{PrimitiveComponent, PrimitiveShape};
+use mela::gfx::RenderContext;
+use mela::nalgebra::{Isometry2, Vector2};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Balls slower than this drop into the cup when they roll over it
+pub const CUP_CAPTURE_SPEED: f64 = 150.;
+
+/// Strokes taken by `ball` up to and including `until`
+pub fn strokes(snapshots: &[Snapshot<f64>], ball: usize, until: Duration) -> u32 {
+    snapshots
+        .iter()
+        .take_while(|s| s.start_time <= until)
+        .flat_map(|s| s.events.iter())
+        .filter(|e| match e {
+            Event::Putt(b, _) => *b == ball,
+            _ => false,
+        })
+        .count() as u32
+}
+
+/// First time `ball` drops into the cup of `hole`, if it ever does
+pub fn holed_at(snapshots: &[Snapshot<f64>], ball: usize, hole: &Hole) -> Option<Duration> {
+    snapshots
+        .iter()
+        .filter_map(|s| snapshot_holed_at(s, ball, hole))
+        .next()
+}
+
+fn snapshot_holed_at(snapshot: &Snapshot<f64>, ball: usize, hole: &Hole) -> Option<Duration> {
+    let body = &snapshot.balls[ball];
+    let start_speed = body.velocity.norm();
+    let to_cup = &hole.cup - &body.position;
+
+    if body.velocity.norm_squared() <= 1.0 {
+        return if to_cup.norm() <= hole.cup_radius {
+            Some(snapshot.start_time)
+        } else {
+            None
+        };
+    }
+
+    // the ball rolls in a straight line, slowing down at a constant rate
+    let deceleration = Snapshot::ball_acceleration(body).norm();
+    let stop_distance = start_speed.powf(2.) / (2. * deceleration);
+    let length = match snapshot.end_time.checked_sub(snapshot.start_time) {
+        Some(duration) if snapshot.end_time < Duration::new(u64::MAX, 0) => {
+            let t = duration.as_secs_f64();
+            (start_speed * t - 0.5 * deceleration * t.powf(2.)).min(stop_distance)
+        }
+        _ => stop_distance,
+    };
+
+    let direction = body.velocity.normalize();
+    let projection = to_cup.dot(&direction);
+    let perpendicular = to_cup.norm_squared() - projection.powf(2.);
+
+    if perpendicular > hole.cup_radius.powf(2.) {
+        return None;
+    }
+
+    let half_chord = (hole.cup_radius.powf(2.) - perpendicular).sqrt();
+    let (entry, exit) = (projection - half_chord, projection + half_chord);
+
+    if exit < 0. || entry > length {
+        return None;
+    }
+
+    // slowest point over the cup is the furthest one
+    let distance = exit.min(length);
+    let speed = (start_speed.powf(2.) - 2. * deceleration * distance)
+        .max(0.)
+        .sqrt();
+
+    if speed <= CUP_CAPTURE_SPEED {
+        let t = (start_speed - speed) / deceleration;
+        Some(snapshot.start_time + Duration::from_secs_f64(t))
+    } else {
+        None
+    }
+}
+
+/// Name for a score relative to par
+pub fn score_name(strokes: u32, par: u32) -> &'static str {
+    if strokes == 1 {
+        return "Hole in one!";
+    }
+
+    match strokes as i64 - par as i64 {
+        i64::MIN..=-3 => "Albatross",
+        -2 => "Eagle",
+        -1 => "Birdie",
+        0 => "Par",
+        1 => "Bogey",
+        2 => "Double bogey",
+        3 => "Triple bogey",
+        _ => "Ouch",
+    }
+}
+
+/// Score relative to par, formatted like golfers do
+pub fn relative_to_par(strokes: u32, par: u32) -> String {
+    match strokes as i64 - par as i64 {
+        0 => "E".to_owned(),
+        d if d > 0 => format!("+{}", d),
+        d => format!("{}", d),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HoleProgress {
+    pub strokes: u32,
+    pub holed_at: Option<Duration>,
+    /// The ball has been seen dropping into the cup
+    pub completed: bool,
+}
+
+/// Keeps `HoleProgress` up to date, and drops the player ball into the cup when it gets there
+pub struct ScoreKeeper {
+    snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+    timer: Rc<RefCell<Duration>>,
+    hole: Rc<Hole>,
+    progress: Rc<RefCell<HoleProgress>>,
+}
+
+impl ScoreKeeper {
+    pub fn new(
+        snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+        timer: Rc<RefCell<Duration>>,
+        hole: Rc<Hole>,
+        progress: Rc<RefCell<HoleProgress>>,
+    ) -> ScoreKeeper {
+        ScoreKeeper {
+            snapshots,
+            timer,
+            hole,
+            progress,
+        }
+    }
+}
+
+impl System<MyWorld> for ScoreKeeper {
+    type SystemData<'a> = (
+        Read<'a, PlayerController>,
+        Read<'a, BallComponent>,
+        Write<'a, Transform<f64>>,
+        Write<'a, PrimitiveComponent>,
+    );
+
+    fn name(&self) -> &'static str {
+        "ScoreKeeper"
+    }
+
+    fn update<'f>(
+        &mut self,
+        (controller, balls, mut transforms, mut primitives): Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
+        let (entity, _) = controller.iter().next().unwrap();
+        let ball = balls.fetch(entity).unwrap();
+        let current_time = *self.timer.borrow();
+        let snapshots = self.snapshots.borrow();
+
+        let holed_at = holed_at(&snapshots, ball.index, &self.hole);
+        let completed = holed_at.map_or(false, |t| t <= current_time);
+
+        {
+            let mut progress = self.progress.borrow_mut();
+            progress.strokes = strokes(&snapshots, ball.index, holed_at.unwrap_or(current_time));
+            progress.holed_at = holed_at;
+            progress.completed = completed;
+        }
+
+        if completed {
+            let (_, transform) = transforms.iter_mut().find(|(e, _)| *e == entity).unwrap();
+            transform.0 = Isometry2::new(Vector2::new(self.hole.cup.x, self.hole.cup.y), 0.);
+
+            let (_, primitive) = primitives.iter_mut().find(|(e, _)| *e == entity).unwrap();
+            let radius = snapshots[0].balls[ball.index].body.radius as f32 * 0.6;
+            primitive.shape = PrimitiveShape::Ball(radius, radius);
+        }
+    }
+}
+
+/// Progress through a course, one hole after another
+#[derive(Clone, Debug)]
+pub struct Round {
+    pub course: Rc<Course>,
+    /// Index of the hole being played
+    pub hole: usize,
+    pub strokes: Vec<Option<u32>>,
+    /// Practice rounds allow taking back shots
+    pub practice: bool,
+    /// Seed the course was generated from, generated courses have no file to edit
+    pub seed: Option<u64>,
+}
+
+impl Round {
+    pub fn new(course: Rc<Course>) -> Round {
+        Round {
+            strokes: vec![None; course.holes.len()],
+            course,
+            hole: 0,
+            practice: false,
+            seed: None,
+        }
+    }
+
+    pub fn current_hole(&self) -> &Hole {
+        &self.course.holes[self.hole]
+    }
+
+    pub fn is_last_hole(&self) -> bool {
+        self.hole + 1 >= self.course.holes.len()
+    }
+
+    /// Records the score for the current hole
+    pub fn record(&mut self, strokes: u32) {
+        self.strokes[self.hole] = Some(strokes);
+    }
+
+    pub fn total_strokes(&self) -> u32 {
+        self.strokes.iter().filter_map(|s| *s).sum()
+    }
+
+    /// Par of the holes played so far
+    pub fn played_par(&self) -> u32 {
+        self.course
+            .holes
+            .iter()
+            .zip(&self.strokes)
+            .filter(|(_, s)| s.is_some())
+            .map(|(h, _)| h.par)
+            .sum()
+    }
+
+    /// Index of the hole played best relative to par, earliest one on ties
+    pub fn best_hole(&self) -> Option<usize> {
+        self.course
+            .holes
+            .iter()
+            .zip(&self.strokes)
+            .enumerate()
+            .filter_map(|(i, (h, s))| s.map(|s| (i, s as i64 - h.par as i64)))
+            .min_by_key(|(i, relative)| (*relative, *i))
+            .map(|(i, _)| i)
+    }
+}
@@ -0,0 +1,118 @@
+//! User settings from `settings.json`, the environment and the command line
+
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SETTINGS_PATH: &str = "settings.json";
+pub const DEFAULT_ENDPOINT: &str = "http://minigolf.srvrs.eu";
+/// Environment variable overriding the endpoint in the settings file
+pub const ENDPOINT_VAR: &str = "MINIGOLF_ENDPOINT";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Lobby server URL
+    pub endpoint: String,
+    /// Hides the multiplayer menu entirely
+    pub offline: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            endpoint: DEFAULT_ENDPOINT.to_owned(),
+            offline: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, a missing file gives the defaults
+    pub fn load(path: &Path) -> Result<Settings, String> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Applies `MINIGOLF_ENDPOINT` if it's set
+    pub fn apply_env(&mut self) {
+        if let Ok(endpoint) = std::env::var(ENDPOINT_VAR) {
+            if !endpoint.is_empty() {
+                self.endpoint = endpoint;
+            }
+        }
+    }
+
+    /// Takes `--endpoint <url>` and `--offline` out of `args`, leaving the rest in order
+    pub fn apply_args(&mut self, args: &mut Vec<String>) -> Result<(), String> {
+        let mut rest = Vec::with_capacity(args.len());
+        let mut iter = std::mem::take(args).into_iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--endpoint" => {
+                    self.endpoint = iter
+                        .next()
+                        .ok_or_else(|| "--endpoint needs a URL".to_owned())?;
+                }
+                "--offline" => self.offline = true,
+                _ => rest.push(arg),
+            }
+        }
+
+        *args = rest;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| (*a).to_owned()).collect()
+    }
+
+    #[test]
+    fn endpoint_and_offline_are_taken_out() {
+        let mut settings = Settings::default();
+        let mut rest = args(&[
+            "--endpoint",
+            "http://localhost:8080",
+            "course.json",
+            "--offline",
+        ]);
+
+        settings.apply_args(&mut rest).unwrap();
+
+        assert_eq!(settings.endpoint, "http://localhost:8080");
+        assert!(settings.offline);
+        assert_eq!(rest, args(&["course.json"]));
+    }
+
+    #[test]
+    fn other_args_are_left_alone() {
+        let mut settings = Settings::default();
+        let mut rest = args(&["--validate", "course.json"]);
+
+        settings.apply_args(&mut rest).unwrap();
+
+        assert_eq!(settings.endpoint, DEFAULT_ENDPOINT);
+        assert!(!settings.offline);
+        assert_eq!(rest, args(&["--validate", "course.json"]));
+    }
+
+    #[test]
+    fn endpoint_needs_a_value() {
+        let mut settings = Settings::default();
+
+        assert!(settings.apply_args(&mut args(&["--endpoint"])).is_err());
+        assert_eq!(settings.endpoint, DEFAULT_ENDPOINT);
+    }
+}
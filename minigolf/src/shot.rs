@@ -0,0 +1,139 @@
+//! Press-drag-release shot gesture and power meter
+
+use api::MAX_PUTT_IMPULSE;
+use mela::gfx::primitives::PrimitiveShape;
+use mela::lyon::lyon_algorithms::path::Path;
+use mela::lyon::lyon_tessellation::math::Point;
+use mela::nalgebra::Vector2;
+
+/// Dragging closer than this to the ball cancels the shot
+pub const CANCEL_RADIUS: f64 = 20.;
+/// Drag distance from the ball that gives a full power shot
+pub const MAX_DRAG: f64 = 250.;
+/// Power curve exponent, values over 1 give finer control over soft shots
+pub const POWER_EXPONENT: f64 = 2.;
+
+/// Gap between the ball and the start of the power meter
+const METER_OFFSET: f32 = 30.;
+const METER_LENGTH: f32 = 120.;
+const METER_WIDTH: f32 = 6.;
+
+#[derive(Clone, Debug)]
+pub enum Shot {
+    Idle,
+    Aiming {
+        impulse: Vector2<f64>,
+        /// 0..1, after the power curve has been applied
+        power: f64,
+    },
+    Fire(Vector2<f64>),
+    Cancelled,
+}
+
+/// Tracks the shot gesture: press to start aiming, drag away from the ball to add power,
+/// release to shoot. Releasing close to the ball cancels the shot.
+#[derive(Debug, Default)]
+pub struct ShotGesture {
+    aiming: bool,
+    was_pressed: bool,
+}
+
+impl ShotGesture {
+    pub fn new() -> ShotGesture {
+        Default::default()
+    }
+
+    /// Drops the current gesture, the button has to be pressed again to start aiming
+    pub fn reset(&mut self) {
+        self.aiming = false;
+    }
+
+    pub fn update(&mut self, pressed: bool, ball: &Vector2<f64>, mouse: &Vector2<f64>) -> Shot {
+        let just_pressed = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+
+        if just_pressed {
+            self.aiming = true;
+        }
+
+        if !self.aiming {
+            return Shot::Idle;
+        }
+
+        let offset = mouse - ball;
+        let drag = offset.norm();
+
+        if drag < CANCEL_RADIUS {
+            if !pressed {
+                self.aiming = false;
+                return Shot::Cancelled;
+            }
+
+            return Shot::Aiming {
+                impulse: Vector2::new(0., 0.),
+                power: 0.,
+            };
+        }
+
+        let power = power_curve((drag - CANCEL_RADIUS) / (MAX_DRAG - CANCEL_RADIUS));
+        let impulse = offset / drag * power * MAX_PUTT_IMPULSE;
+
+        if pressed {
+            Shot::Aiming { impulse, power }
+        } else {
+            self.aiming = false;
+            Shot::Fire(impulse)
+        }
+    }
+}
+
+/// Maps linear drag fraction into shot power
+pub fn power_curve(drag_fraction: f64) -> f64 {
+    drag_fraction.max(0.).min(1.).powf(POWER_EXPONENT)
+}
+
+/// Color of the hit indicator, from white to red as the power goes up
+pub fn indicator_color(power: f64) -> [f32; 4] {
+    let power = power as f32;
+    [1., 1. - power, 1. - power, 1.]
+}
+
+/// Aim line with a power meter along it
+pub fn indicator_shape(ball: &Vector2<f64>, impulse: &Vector2<f64>, power: f64) -> PrimitiveShape {
+    if impulse.norm_squared() == 0. {
+        return PrimitiveShape::Path(Path::new());
+    }
+
+    let direction = impulse.normalize();
+    let (dx, dy) = (direction.x as f32, direction.y as f32);
+    let (nx, ny) = (-dy * METER_WIDTH, dx * METER_WIDTH);
+    let (bx, by) = (ball.x as f32, ball.y as f32);
+
+    let along = |distance: f32, side: f32| {
+        Point::new(
+            bx + dx * distance + nx * side,
+            by + dy * distance + ny * side,
+        )
+    };
+
+    let meter_start = METER_OFFSET;
+    let meter_end = METER_OFFSET + METER_LENGTH;
+    let filled = METER_OFFSET + METER_LENGTH * power as f32;
+
+    let mut path = Path::builder();
+
+    // meter outline
+    path.move_to(along(meter_start, -1.));
+    path.line_to(along(meter_end, -1.));
+    path.line_to(along(meter_end, 1.));
+    path.line_to(along(meter_start, 1.));
+    path.close();
+
+    // filled part of the meter
+    for side in &[-0.5, 0., 0.5] {
+        path.move_to(along(meter_start, *side));
+        path.line_to(along(filled, *side));
+    }
+
+    PrimitiveShape::Path(path.build())
+}
@@ -0,0 +1,964 @@
+//! course editor
+
+use crate::course::{self, Course, CourseFile, Hole, Polyline, RackBall, Warning};
+use crate::physics::{Motion, Surface, Zone};
+use crate::resources::Resources;
+use crate::score::Round;
+use crate::states::{Play, Wrapper};
+use crate::world::MyWorld;
+use mela::debug::{DebugContext, DebugDrawable};
+use mela::ecs::component::Transform;
+use mela::ecs::system::{Read, SystemCaller, Write};
+use mela::ecs::world::World;
+use mela::ecs::{Component, System};
+use mela::game::IoState;
+use mela::gfx::primitives::{PrimitiveComponent, PrimitiveShape};
+use mela::gfx::RenderContext;
+use mela::lyon::lyon_algorithms::path::Path;
+use mela::lyon::lyon_tessellation::math::Point;
+use mela::nalgebra::{Isometry2, Point2, Vector2};
+use mela::state::State;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How close to a vertex or a wall the mouse has to be to pick it
+const PICK_RADIUS: f64 = 8.;
+const GRID_SIZE: f64 = 10.;
+/// The grid is drawn from the origin up to here
+const GRID_EXTENT: [f64; 2] = [1600., 1000.];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tool {
+    Walls,
+    Zones,
+    Tee,
+    Cup,
+    Balls,
+    Delete,
+}
+
+/// Motion picked for a wall in the wall panel
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MotionKind {
+    Static,
+    Rotate,
+    Slide,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shape {
+    Wall(usize),
+    Zone(usize),
+}
+
+/// Something that can be dragged around
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Handle {
+    Vertex(Shape, usize),
+    Tee,
+    Cup,
+    Ball(usize),
+}
+
+fn distance_to_segment(p: &Point2<f64>, a: &Point2<f64>, b: &Point2<f64>) -> (f64, Point2<f64>) {
+    let ab = b - a;
+    let t = if ab.norm_squared() > 0. {
+        ((p - a).dot(&ab) / ab.norm_squared()).max(0.).min(1.)
+    } else {
+        0.
+    };
+
+    let closest = a + ab * t;
+    ((p - closest).norm(), closest)
+}
+
+/// The hole being edited, shared with `EditorView`
+struct Sketch {
+    hole: Hole,
+    /// Wall or zone that clicks on empty space add vertices to
+    active: Option<Shape>,
+    /// Wall or zone shown in the properties
+    selected: Option<Shape>,
+    snap_to_grid: bool,
+    /// Problems found by the last check
+    warnings: Vec<Warning>,
+}
+
+impl Sketch {
+    fn new(hole: Hole) -> Sketch {
+        Sketch {
+            hole,
+            active: None,
+            selected: None,
+            snap_to_grid: true,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn snap(&self, p: Point2<f64>) -> Point2<f64> {
+        if self.snap_to_grid {
+            Point2::new(
+                (p.x / GRID_SIZE).round() * GRID_SIZE,
+                (p.y / GRID_SIZE).round() * GRID_SIZE,
+            )
+        } else {
+            p
+        }
+    }
+
+    fn points(&self, shape: Shape) -> &Vec<Point2<f64>> {
+        match shape {
+            Shape::Wall(i) => &self.hole.walls[i].points,
+            Shape::Zone(i) => &self.hole.zones[i].polygon,
+        }
+    }
+
+    fn points_mut(&mut self, shape: Shape) -> &mut Vec<Point2<f64>> {
+        match shape {
+            Shape::Wall(i) => &mut self.hole.walls[i].points,
+            Shape::Zone(i) => &mut self.hole.zones[i].polygon,
+        }
+    }
+
+    fn is_closed(&self, shape: Shape) -> bool {
+        match shape {
+            Shape::Wall(i) => self.hole.walls[i].closed,
+            Shape::Zone(_) => true,
+        }
+    }
+
+    /// Walls need two vertices to be drawn, zones three
+    fn is_degenerate(&self, shape: Shape) -> bool {
+        match shape {
+            Shape::Wall(_) => self.points(shape).len() < 2,
+            Shape::Zone(_) => self.points(shape).len() < 3,
+        }
+    }
+
+    /// Walls for `Tool::Walls`, zones for `Tool::Zones`, both for `Tool::Delete`
+    fn shapes(&self, tool: Tool) -> Vec<Shape> {
+        let walls = (0..self.hole.walls.len()).map(Shape::Wall);
+        let zones = (0..self.hole.zones.len()).map(Shape::Zone);
+
+        match tool {
+            Tool::Walls => walls.collect(),
+            Tool::Zones => zones.collect(),
+            Tool::Delete => walls.chain(zones).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn position(&self, handle: Handle) -> Point2<f64> {
+        match handle {
+            Handle::Vertex(shape, i) => self.points(shape)[i],
+            Handle::Tee => self.hole.tee,
+            Handle::Cup => self.hole.cup,
+            Handle::Ball(i) => self.hole.rack[i].position,
+        }
+    }
+
+    fn set_position(&mut self, handle: Handle, p: Point2<f64>) {
+        match handle {
+            Handle::Vertex(shape, i) => self.points_mut(shape)[i] = p,
+            Handle::Tee => self.hole.tee = p,
+            Handle::Cup => self.hole.cup = p,
+            Handle::Ball(i) => self.hole.rack[i].position = p,
+        }
+    }
+
+    /// Closest handle the tool can pick up
+    fn handle_at(&self, p: &Point2<f64>, tool: Tool) -> Option<Handle> {
+        let mut handles: Vec<Handle> = self
+            .shapes(tool)
+            .into_iter()
+            .flat_map(|shape| (0..self.points(shape).len()).map(move |i| Handle::Vertex(shape, i)))
+            .collect();
+
+        match tool {
+            Tool::Tee => handles.push(Handle::Tee),
+            Tool::Cup => handles.push(Handle::Cup),
+            Tool::Balls | Tool::Delete => {
+                handles.extend((0..self.hole.rack.len()).map(Handle::Ball))
+            }
+            _ => (),
+        }
+
+        handles
+            .into_iter()
+            .map(|h| (h, (self.position(h) - p).norm()))
+            .filter(|(_, distance)| *distance <= PICK_RADIUS)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(h, _)| h)
+    }
+
+    /// Closest wall or zone edge, with the index a vertex splitting it should be inserted at
+    fn edge_at(&self, p: &Point2<f64>, tool: Tool) -> Option<(Shape, usize, Point2<f64>)> {
+        let mut closest: Option<(f64, Shape, usize, Point2<f64>)> = None;
+
+        for shape in self.shapes(tool) {
+            let points = self.points(shape);
+            let mut edges: Vec<(usize, usize)> = (1..points.len()).map(|i| (i - 1, i)).collect();
+
+            if self.is_closed(shape) && points.len() > 2 {
+                edges.push((points.len() - 1, 0));
+            }
+
+            for (a, b) in edges {
+                let (distance, on_edge) = distance_to_segment(p, &points[a], &points[b]);
+
+                if distance <= PICK_RADIUS && closest.map_or(true, |c| distance < c.0) {
+                    closest = Some((distance, shape, a + 1, on_edge));
+                }
+            }
+        }
+
+        closest.map(|(_, shape, index, on_edge)| (shape, index, on_edge))
+    }
+
+    fn remove_shape(&mut self, shape: Shape) {
+        match shape {
+            Shape::Wall(i) => {
+                self.hole.walls.remove(i);
+            }
+            Shape::Zone(i) => {
+                self.hole.zones.remove(i);
+            }
+        }
+
+        // indices after the removed shape shift down
+        let fix = |other: Option<Shape>| match (other, shape) {
+            (Some(o), s) if o == s => None,
+            (Some(Shape::Wall(o)), Shape::Wall(i)) if o > i => Some(Shape::Wall(o - 1)),
+            (Some(Shape::Zone(o)), Shape::Zone(i)) if o > i => Some(Shape::Zone(o - 1)),
+            (other, _) => other,
+        };
+
+        self.active = fix(self.active);
+        self.selected = fix(self.selected);
+    }
+
+    fn delete(&mut self, handle: Handle) {
+        match handle {
+            Handle::Vertex(shape, i) => {
+                self.points_mut(shape).remove(i);
+
+                if self.is_degenerate(shape) && self.active != Some(shape) {
+                    self.remove_shape(shape);
+                }
+            }
+            Handle::Ball(i) => {
+                self.hole.rack.remove(i);
+            }
+            // the hole can't do without these
+            Handle::Tee | Handle::Cup => (),
+        }
+    }
+
+    /// Stops adding vertices to the active shape, dropping it if it's too short
+    fn finish(&mut self) {
+        if let Some(shape) = self.active.take() {
+            if self.is_degenerate(shape) {
+                self.remove_shape(shape);
+            }
+        }
+    }
+
+    /// Starts a new wall or zone, or extends the active one
+    fn add_vertex(&mut self, p: Point2<f64>, tool: Tool) -> Handle {
+        let shape = match (self.active, tool) {
+            (Some(shape @ Shape::Wall(_)), Tool::Walls) => shape,
+            (Some(shape @ Shape::Zone(_)), Tool::Zones) => shape,
+            _ => {
+                self.finish();
+
+                if tool == Tool::Walls {
+                    self.hole.walls.push(Polyline {
+                        points: Vec::new(),
+                        closed: false,
+                        motion: None,
+                        one_way: false,
+                    });
+                    Shape::Wall(self.hole.walls.len() - 1)
+                } else {
+                    self.hole.zones.push(Zone {
+                        polygon: Vec::new(),
+                        surface: Surface::default(),
+                    });
+                    Shape::Zone(self.hole.zones.len() - 1)
+                }
+            }
+        };
+
+        self.active = Some(shape);
+        self.selected = Some(shape);
+        self.points_mut(shape).push(p);
+
+        Handle::Vertex(shape, self.points(shape).len() - 1)
+    }
+
+    /// Picks up whatever is under the mouse, creating it first when needed
+    fn press(&mut self, mouse: Point2<f64>, tool: Tool) -> Option<Handle> {
+        let p = self.snap(mouse);
+
+        if tool == Tool::Delete {
+            if let Some(handle) = self.handle_at(&mouse, tool) {
+                self.delete(handle);
+            }
+
+            return None;
+        }
+
+        if let Some(handle) = self.handle_at(&mouse, tool) {
+            if let Handle::Vertex(shape, _) = handle {
+                self.selected = Some(shape);
+            }
+
+            return Some(handle);
+        }
+
+        match tool {
+            Tool::Walls | Tool::Zones => {
+                if let Some((shape, index, on_edge)) = self.edge_at(&mouse, tool) {
+                    let p = self.snap(on_edge);
+                    self.points_mut(shape).insert(index, p);
+                    self.selected = Some(shape);
+
+                    return Some(Handle::Vertex(shape, index));
+                }
+
+                Some(self.add_vertex(p, tool))
+            }
+            Tool::Tee => Some(Handle::Tee),
+            Tool::Cup => Some(Handle::Cup),
+            Tool::Balls => {
+                self.hole.rack.push(RackBall {
+                    position: p,
+                    radius: self.hole.ball_radius,
+                    mass: 1.,
+                    color: None,
+                });
+                Some(Handle::Ball(self.hole.rack.len() - 1))
+            }
+            Tool::Delete => None,
+        }
+    }
+
+    /// The hole without any half drawn walls or zones
+    fn finished_hole(&self) -> Hole {
+        let mut hole = self.hole.clone();
+        hole.walls.retain(|w| w.points.len() >= 2);
+        hole.zones.retain(|z| z.polygon.len() >= 3);
+        hole
+    }
+}
+
+#[derive(Debug)]
+enum EditorLayer {
+    Grid,
+    Zones,
+    Walls,
+    Vertices,
+    Rack,
+    Tee,
+    Cup,
+    Warnings,
+}
+
+impl Component for EditorLayer {}
+
+/// Draws the sketch
+struct EditorView {
+    sketch: Rc<RefCell<Sketch>>,
+}
+
+/// Corners of a square centered on `p`
+fn square(p: &Point2<f64>, size: f32) -> Vec<Point> {
+    let (x, y) = (p.x as f32, p.y as f32);
+
+    vec![
+        Point::new(x - size, y - size),
+        Point::new(x + size, y - size),
+        Point::new(x + size, y + size),
+        Point::new(x - size, y + size),
+    ]
+}
+
+fn points(points: &[Point2<f64>]) -> Vec<Point> {
+    points
+        .iter()
+        .map(|p| Point::new(p.x as f32, p.y as f32))
+        .collect()
+}
+
+impl System<MyWorld> for EditorView {
+    type SystemData<'a> = (
+        Read<'a, EditorLayer>,
+        Write<'a, Transform<f64>>,
+        Write<'a, PrimitiveComponent>,
+    );
+
+    fn name(&self) -> &'static str {
+        "EditorView"
+    }
+
+    fn update<'f>(
+        &mut self,
+        (layers, mut transforms, mut primitives): Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
+        let sketch = self.sketch.borrow();
+        let hole = &sketch.hole;
+
+        for (entity, layer) in layers.iter() {
+            let mut path = Path::builder();
+            let mut polylines: Vec<(Vec<Point>, bool)> = Vec::new();
+
+            match layer {
+                EditorLayer::Grid => {
+                    if sketch.snap_to_grid {
+                        let [width, height] = GRID_EXTENT;
+                        let step = GRID_SIZE as f32 * 5.;
+
+                        for i in 0..=(width as f32 / step) as usize {
+                            let x = i as f32 * step;
+                            path.move_to(Point::new(x, 0.));
+                            path.line_to(Point::new(x, height as f32));
+                        }
+
+                        for i in 0..=(height as f32 / step) as usize {
+                            let y = i as f32 * step;
+                            path.move_to(Point::new(0., y));
+                            path.line_to(Point::new(width as f32, y));
+                        }
+                    }
+                }
+                EditorLayer::Zones => {
+                    polylines.extend(hole.zones.iter().map(|z| (points(&z.polygon), true)));
+
+                    for teleporter in &hole.teleporters {
+                        let radius = teleporter.radius as f32;
+                        polylines.push((square(&teleporter.entry, radius), true));
+                        polylines.push((square(&teleporter.exit, radius / 2.), true));
+                    }
+                }
+                EditorLayer::Walls => {
+                    polylines.extend(hole.walls.iter().map(|w| (points(&w.points), w.closed)));
+
+                    // gates and triggers can't be edited here, but they are shown
+                    for gate in &hole.gates {
+                        polylines.extend(gate.walls.iter().map(|w| (points(&w.points), w.closed)));
+                    }
+
+                    for trigger in &hole.triggers {
+                        let line = [trigger.line.start, trigger.line.end];
+                        polylines.push((points(&line), false));
+                    }
+                }
+                EditorLayer::Vertices => {
+                    for shape in sketch.shapes(Tool::Delete) {
+                        let size = if sketch.selected == Some(shape) {
+                            4.
+                        } else {
+                            2.
+                        };
+
+                        for p in sketch.points(shape) {
+                            polylines.push((square(p, size), true));
+                        }
+                    }
+                }
+                EditorLayer::Rack => {
+                    for ball in &hole.rack {
+                        polylines.push((square(&ball.position, ball.radius as f32), true));
+                    }
+                }
+                EditorLayer::Warnings => {
+                    for warning in &sketch.warnings {
+                        polylines.push((square(&warning.position, 6.), true));
+                    }
+                }
+                EditorLayer::Tee | EditorLayer::Cup => {
+                    let (position, radius) = match layer {
+                        EditorLayer::Tee => (hole.tee, hole.ball_radius),
+                        _ => (hole.cup, hole.cup_radius),
+                    };
+
+                    let (_, transform) = transforms.iter_mut().find(|(e, _)| *e == entity).unwrap();
+                    transform.0 = Isometry2::new(Vector2::new(position.x, position.y), 0.);
+
+                    let (_, primitive) = primitives.iter_mut().find(|(e, _)| *e == entity).unwrap();
+                    primitive.shape = PrimitiveShape::Ball(radius as f32, radius as f32);
+
+                    continue;
+                }
+            }
+
+            for (points, closed) in polylines {
+                for (i, p) in points.iter().enumerate() {
+                    if i == 0 {
+                        path.move_to(*p);
+                    } else {
+                        path.line_to(*p);
+                    }
+                }
+
+                if closed && points.len() > 2 {
+                    path.close();
+                }
+            }
+
+            let (_, primitive) = primitives.iter_mut().find(|(e, _)| *e == entity).unwrap();
+            primitive.shape = PrimitiveShape::Path(path.build());
+        }
+    }
+}
+
+pub struct Editor {
+    resources: Rc<Resources>,
+    course: Course,
+    /// Index of the hole in the sketch
+    hole: usize,
+    /// Where the course is saved to
+    path: PathBuf,
+    sketch: Rc<RefCell<Sketch>>,
+    tool: Tool,
+    dragging: Option<Handle>,
+    was_pressed: bool,
+    /// Result of the last save or check
+    status: Option<String>,
+    /// Reachability search of the last check, running in the background
+    reachability: Option<Receiver<Option<Warning>>>,
+    world: MyWorld,
+    systems: Vec<Box<dyn SystemCaller<MyWorld>>>,
+}
+
+impl Editor {
+    pub fn new(resources: Rc<Resources>, course: Course, hole: usize) -> Editor {
+        let sketch = Rc::new(RefCell::new(Sketch::new(course.holes[hole].clone())));
+
+        let layers = [
+            (EditorLayer::Grid, [0.3, 0.3, 0.3, 1.]),
+            (EditorLayer::Zones, [0.9, 0.8, 0.4, 1.]),
+            (EditorLayer::Cup, [0.3, 1., 0.3, 1.]),
+            (EditorLayer::Walls, [0., 0.2, 1., 1.]),
+            (EditorLayer::Vertices, [1., 1., 1., 1.]),
+            (EditorLayer::Rack, [0.8, 0.8, 0.8, 1.]),
+            (EditorLayer::Tee, [0., 1., 1., 1.]),
+            (EditorLayer::Warnings, [1., 0., 0., 1.]),
+        ];
+
+        let mut world = MyWorld::new()
+            .register::<Transform<f64>>()
+            .register::<PrimitiveComponent>()
+            .register::<EditorLayer>();
+
+        for (layer, color) in layers.iter().cloned() {
+            world = world
+                .add_entity()
+                .with_component(Transform(Isometry2::translation(0., 0.)))
+                .with_component(PrimitiveComponent {
+                    color,
+                    shape: PrimitiveShape::Path(Path::new()),
+                })
+                .with_component(layer)
+                .build();
+        }
+
+        Editor {
+            path: course::save_path(&resources.course_path),
+            systems: vec![
+                Box::new(EditorView {
+                    sketch: Rc::clone(&sketch),
+                }) as Box<dyn SystemCaller<MyWorld>>,
+                Box::new(mela::gfx::primitives::PrimitiveRenderer::new()),
+            ],
+            resources,
+            course,
+            hole,
+            sketch,
+            tool: Tool::Walls,
+            dragging: None,
+            was_pressed: false,
+            status: None,
+            reachability: None,
+            world,
+        }
+    }
+
+    /// Writes the sketch back into the course
+    fn commit(&mut self) {
+        let mut sketch = self.sketch.borrow_mut();
+        sketch.finish();
+        self.course.holes[self.hole] = sketch.finished_hole();
+    }
+
+    fn switch_hole(&mut self, hole: usize) {
+        self.commit();
+        self.hole = hole;
+        *self.sketch.borrow_mut() = Sketch::new(self.course.holes[hole].clone());
+        self.dragging = None;
+        // the search was for the hole switched away from
+        self.reachability = None;
+    }
+
+    fn save(&mut self) {
+        self.commit();
+
+        let file = CourseFile::from(&self.course);
+
+        // check that the file can be loaded back before overwriting anything
+        let result = file
+            .clone()
+            .into_course(&self.path)
+            .and_then(|_| file.save(&self.path));
+
+        self.status = Some(match result {
+            Ok(()) => format!("Saved to {}", self.path.display()),
+            Err(e) => e.to_string(),
+        });
+    }
+
+    /// Runs the validator on the hole being edited. The lints are quick and done right away,
+    /// the reachability search takes a while and runs on its own thread.
+    fn check(&mut self) {
+        let mut sketch = self.sketch.borrow_mut();
+        sketch.finish();
+        let hole = sketch.finished_hole();
+        sketch.warnings = course::lint_hole(self.hole, &hole);
+
+        if course::needs_reachability(&hole, &sketch.warnings) {
+            let index = self.hole;
+            let (sender, searching) = channel();
+
+            thread::spawn(move || {
+                let _ = sender.send(course::check_reachable(index, &hole));
+            });
+
+            self.reachability = Some(searching);
+            self.status = Some("Checking if the cup can be reached...".to_owned());
+        } else {
+            self.reachability = None;
+            self.status = Some(problems_found(sketch.warnings.len()));
+        }
+    }
+
+    /// Adds the result of the reachability search to the warnings once it's done
+    fn poll_reachability(&mut self) {
+        let result = match &self.reachability {
+            Some(searching) => searching.try_recv(),
+            None => return,
+        };
+
+        match result {
+            Ok(warning) => {
+                let mut sketch = self.sketch.borrow_mut();
+                sketch.warnings.extend(warning);
+                self.status = Some(problems_found(sketch.warnings.len()));
+            }
+            Err(TryRecvError::Empty) => return,
+            // the search panicked
+            Err(TryRecvError::Disconnected) => {
+                self.status = Some("Reachability check failed".to_owned());
+            }
+        }
+
+        self.reachability = None;
+    }
+
+    /// Plays only the hole being edited, coming back here when done
+    fn test_play(mut self) -> Play {
+        self.commit();
+
+        let course = Course {
+            holes: vec![self.course.holes[self.hole].clone()],
+            ..self.course.clone()
+        };
+
+        let mut round = Round::new(Rc::new(course));
+        round.practice = true;
+
+        Play::new(Rc::clone(&self.resources), round).with_editor(Some(Box::new(self)))
+    }
+}
+
+fn problems_found(count: usize) -> String {
+    match count {
+        0 => "No problems found".to_owned(),
+        n => format!("{} problems found", n),
+    }
+}
+
+impl DebugDrawable for Editor {}
+
+impl State for Editor {
+    type Wrapper = Wrapper;
+
+    fn name(&self) -> &str {
+        "Editor"
+    }
+
+    fn update(
+        mut self,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> Self::Wrapper {
+        let ui = &debug_ctx.ui;
+
+        // clicks on the UI are not meant for the hole
+        let pressed = io_state.mouse_buttons[0] && !ui.io().want_capture_mouse;
+        let mouse = Point2::new(
+            io_state.mouse_position[0] as f64,
+            io_state.mouse_position[1] as f64,
+        );
+
+        {
+            let mut sketch = self.sketch.borrow_mut();
+
+            if pressed && !self.was_pressed {
+                self.dragging = sketch.press(mouse, self.tool);
+            } else if pressed {
+                if let Some(handle) = self.dragging {
+                    let p = sketch.snap(mouse);
+                    sketch.set_position(handle, p);
+                }
+            } else {
+                self.dragging = None;
+            }
+        }
+
+        self.was_pressed = pressed;
+
+        use mela::imgui::im_str;
+        ui.text(im_str!(
+            "Editing {} - hole {}/{}",
+            self.course.name,
+            self.hole + 1,
+            self.course.holes.len()
+        ));
+
+        {
+            let mut sketch = self.sketch.borrow_mut();
+
+            let mut par = sketch.hole.par as i32;
+            if ui.input_int(im_str!("Par"), &mut par).build() {
+                sketch.hole.par = par.max(1) as u32;
+            }
+
+            ui.separator();
+
+            let tools = [
+                (im_str!("Walls"), Tool::Walls),
+                (im_str!("Zones"), Tool::Zones),
+                (im_str!("Tee"), Tool::Tee),
+                (im_str!("Cup"), Tool::Cup),
+                (im_str!("Balls"), Tool::Balls),
+                (im_str!("Delete"), Tool::Delete),
+            ];
+
+            for (label, tool) in tools.iter() {
+                if ui.radio_button(label, &mut self.tool, *tool) {
+                    sketch.finish();
+                }
+            }
+
+            ui.checkbox(im_str!("Snap to grid"), &mut sketch.snap_to_grid);
+
+            if let Some(shape) = sketch.active {
+                let label = match shape {
+                    Shape::Wall(_) => im_str!("Finish wall"),
+                    Shape::Zone(_) => im_str!("Finish zone"),
+                };
+
+                if ui.button(label, [100., 25.]) {
+                    sketch.finish();
+                }
+            }
+
+            match sketch.selected {
+                Some(Shape::Wall(i)) => {
+                    ui.separator();
+                    ui.text(im_str!("Wall {}", i + 1));
+                    ui.checkbox(im_str!("Closed"), &mut sketch.hole.walls[i].closed);
+                    ui.checkbox(im_str!("One way"), &mut sketch.hole.walls[i].one_way);
+
+                    let wall = &mut sketch.hole.walls[i];
+                    let current = match wall.motion {
+                        None => MotionKind::Static,
+                        Some(Motion::Rotate { .. }) => MotionKind::Rotate,
+                        Some(Motion::Slide { .. }) => MotionKind::Slide,
+                    };
+                    let mut kind = current;
+
+                    ui.radio_button(im_str!("Static"), &mut kind, MotionKind::Static);
+                    ui.radio_button(im_str!("Rotate"), &mut kind, MotionKind::Rotate);
+                    ui.radio_button(im_str!("Slide"), &mut kind, MotionKind::Slide);
+
+                    if kind != current {
+                        // spin around the middle of the wall, or slide a bit to the side
+                        let count = wall.points.len().max(1) as f64;
+                        let center = wall
+                            .points
+                            .iter()
+                            .fold(Point2::origin(), |sum, p| sum + p.coords / count);
+
+                        wall.motion = match kind {
+                            MotionKind::Static => None,
+                            MotionKind::Rotate => Some(Motion::Rotate {
+                                pivot: center,
+                                speed: 90f64.to_radians(),
+                            }),
+                            MotionKind::Slide => Some(Motion::Slide {
+                                offset: Vector2::new(100., 0.),
+                                period: 3.,
+                                phase: 0.,
+                            }),
+                        };
+                    }
+
+                    match &mut wall.motion {
+                        Some(Motion::Rotate { speed, .. }) => {
+                            let mut degrees = speed.to_degrees() as f32;
+
+                            if ui
+                                .input_float(im_str!("Degrees per second"), &mut degrees)
+                                .build()
+                                && degrees != 0.
+                            {
+                                *speed = (degrees as f64).to_radians();
+                            }
+                        }
+                        Some(Motion::Slide { offset, period, .. }) => {
+                            let mut x = offset.x as f32;
+                            let mut y = offset.y as f32;
+                            let mut seconds = *period as f32;
+
+                            if ui.input_float(im_str!("Offset x"), &mut x).build() {
+                                offset.x = x as f64;
+                            }
+
+                            if ui.input_float(im_str!("Offset y"), &mut y).build() {
+                                offset.y = y as f64;
+                            }
+
+                            if ui.input_float(im_str!("Period"), &mut seconds).build() {
+                                *period = (seconds as f64).max(0.1);
+                            }
+                        }
+                        None => (),
+                    }
+                }
+                Some(Shape::Zone(i)) => {
+                    ui.separator();
+                    ui.text(im_str!("Zone {}", i + 1));
+
+                    let surface = &mut sketch.hole.zones[i].surface;
+                    let mut friction = surface.friction as f32;
+                    let mut restitution = surface.restitution as f32;
+
+                    if ui.input_float(im_str!("Friction"), &mut friction).build() {
+                        surface.friction = friction.max(0.) as f64;
+                    }
+
+                    if ui
+                        .input_float(im_str!("Restitution"), &mut restitution)
+                        .build()
+                    {
+                        surface.restitution = restitution.max(0.).min(1.) as f64;
+                    }
+                }
+                None => (),
+            }
+
+            if let Some(shape) = sketch.selected {
+                if ui.button(im_str!("Delete shape"), [100., 25.]) {
+                    sketch.remove_shape(shape);
+                }
+            }
+        }
+
+        ui.separator();
+
+        if self.hole > 0 && ui.button(im_str!("Previous hole"), [110., 25.]) {
+            self.switch_hole(self.hole - 1);
+        }
+
+        if self.hole + 1 < self.course.holes.len() && ui.button(im_str!("Next hole"), [110., 25.]) {
+            self.switch_hole(self.hole + 1);
+        }
+
+        if ui.button(im_str!("New hole"), [110., 25.]) {
+            let previous = self.course.holes[self.hole].clone();
+
+            self.course.holes.insert(
+                self.hole + 1,
+                Hole {
+                    name: format!("Hole {}", self.course.holes.len() + 1),
+                    walls: Vec::new(),
+                    zones: Vec::new(),
+                    teleporters: Vec::new(),
+                    gates: Vec::new(),
+                    triggers: Vec::new(),
+                    rack: Vec::new(),
+                    ..previous
+                },
+            );
+
+            self.switch_hole(self.hole + 1);
+        }
+
+        ui.separator();
+
+        if ui.button(im_str!("Check hole"), [110., 25.]) {
+            self.check();
+        }
+
+        if ui.button(im_str!("Test play"), [110., 25.]) {
+            return Wrapper::Play(self.test_play());
+        }
+
+        if ui.button(im_str!("Save"), [110., 25.]) {
+            self.save();
+        }
+
+        if ui.button(im_str!("Exit editor"), [110., 25.]) {
+            // keep playing the edited course, saved or not
+            self.commit();
+
+            let mut round = Round::new(Rc::new(self.course));
+            round.hole = self.hole;
+
+            return Wrapper::Play(Play::new(self.resources, round));
+        }
+
+        self.poll_reachability();
+
+        if let Some(status) = &self.status {
+            ui.text(im_str!("{}", status));
+        }
+
+        for warning in &self.sketch.borrow().warnings {
+            ui.text(im_str!("{}", warning));
+        }
+
+        for system in &mut self.systems {
+            system.dispatch(&self.world, delta, io_state, render_ctx, debug_ctx);
+        }
+
+        Wrapper::Editor(self)
+    }
+
+    fn redraw(&self, render_ctx: &mut RenderContext, debug_ctx: &mut DebugContext) {
+        for system in &self.systems {
+            system.render(render_ctx);
+        }
+    }
+}
@@ -1,10 +1,18 @@
-use crate::api::{GetUpdate, PublicStates};
-use crate::physics::{Ball, BallComponent, PhysicsAnimator, PhysicsBody, Snapshot, Wall};
+use crate::clock::{GameClock, RoundTrip};
+use crate::course::CourseError;
+use crate::net::{ApiError, Backoff, ConnectionState, NetWorker, Pending, Subscription};
+use crate::physics::{
+    simulate, Ball, BallComponent, PhysicsAnimator, PhysicsBody, Snapshot, Surface, Wall,
+};
 use crate::player::{
-    HitIndicator, LineDrawer, MultiplayerInput, PlayerController, PlayerInput, WallComponent,
+    HitIndicator, LineDrawer, MultiplayerInput, PlayerController, PlayerInput, PuttLog, PuttStatus,
+    WallComponent,
 };
-use crate::states::{walls, Play, Wrapper};
+use crate::resources::{lobby_courses, Resources, ARENA_COURSE_PATH, COURSE_DIR};
+use crate::score::Round;
+use crate::states::{Play, Wrapper};
 use crate::world::MyWorld;
+use api::{GetUpdate, HostAction, HostCommand, Player, PublicStates, PLAY_TIME, PUTTS_PER_PLAYER};
 use mela::debug::{DebugContext, DebugDrawable};
 use mela::ecs::component::Transform;
 use mela::ecs::system::SystemCaller;
@@ -17,8 +25,8 @@ use mela::itertools::Itertools;
 use mela::lyon::lyon_algorithms::path::Path;
 use mela::nalgebra::{Isometry2, Point2, Vector2};
 use mela::state::State;
-use reqwest::blocking::Client;
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 use uuid::Uuid;
@@ -31,16 +39,60 @@ pub enum GameState {
     GameOver(Duration),
 }
 
+/// What the client knows about its lobby besides the game state
+#[derive(Clone, Debug, Default)]
+pub struct LobbyInfo {
+    /// Join code of a private lobby
+    pub code: Option<String>,
+    pub host: bool,
+    /// Course file chosen by the host, the arena when none
+    pub course: Option<String>,
+    /// Ids of the players still in the lobby
+    pub players: Vec<usize>,
+}
+
+impl LobbyInfo {
+    pub fn update(&mut self, state: &PublicStates) {
+        let (players, course) = match state {
+            PublicStates::WaitingForPlayers(s) => (&s.players, &s.course),
+            PublicStates::Warmup(s) => (&s.players, &s.course),
+            PublicStates::Play(s) => (&s.players, &s.course),
+            PublicStates::GameOver(_) => return,
+        };
+
+        self.players = players.iter().map(|p| p.id).collect();
+        self.course = course.clone();
+    }
+}
+
 pub struct Multiplay {
+    resources: Rc<Resources>,
+    lobby: Rc<RefCell<LobbyInfo>>,
+    /// Course the world was built for, it's built again when the host picks another one
+    course: Option<String>,
+    course_error: Option<CourseError>,
+    /// Courses the host can choose from, empty for everyone else
+    courses: Vec<(String, usize)>,
+    host_request: Option<Pending<PublicStates>>,
+    host_error: Option<ApiError>,
     game_state: Rc<RefCell<GameState>>,
-    client: Rc<Client>,
+    connection: Rc<RefCell<ConnectionState>>,
+    putt_status: Rc<RefCell<PuttStatus>>,
+    clock: Rc<RefCell<GameClock>>,
     uuid: Uuid,
+    game_id: usize,
     own_ball_index: usize,
     world: MyWorld,
     systems: Vec<Box<dyn SystemCaller<MyWorld>>>,
 }
 impl Multiplay {
-    pub fn new(client: Client, uuid: Uuid, game_id: usize, own_ball_index: usize) -> Multiplay {
+    pub fn new(
+        resources: Rc<Resources>,
+        uuid: Uuid,
+        game_id: usize,
+        own_ball_index: usize,
+        lobby: LobbyInfo,
+    ) -> Multiplay {
         let player_colors = [
             [1., 0., 0.98, 1.0],
             [0.46, 0., 1., 1.0],
@@ -51,10 +103,33 @@ impl Multiplay {
             [1.0, 0.96, 0., 1.],
         ];
 
-        let mut client = Rc::new(client);
         let mut timer = Rc::new(RefCell::new(Duration::new(0, 0)));
         let mut snapshots = Vec::new();
-        let walls = walls();
+        // a course that doesn't load is reported, and the arena is shown behind the report
+        let (course, mut course_error) = match resources.lobby_course(lobby.course.as_deref()) {
+            Ok(course) => (course, None),
+            Err(e) => (Rc::clone(&resources.arena), Some(e)),
+        };
+        let hole = &course.holes[0];
+
+        // without a ball of their own there is nothing to play, so nothing is run either
+        let ball_count = 1 + hole.rack.len();
+        let has_ball = own_ball_index < ball_count;
+        if !has_ball && course_error.is_none() {
+            course_error = Some(CourseError::Invalid {
+                path: match &lobby.course {
+                    Some(name) => PathBuf::from(COURSE_DIR).join(name),
+                    None => PathBuf::from(ARENA_COURSE_PATH),
+                },
+                hole: Some(0),
+                message: format!(
+                    "there are {} balls, but you were given ball {}",
+                    ball_count,
+                    own_ball_index + 1
+                ),
+            });
+        }
+        let walls = Rc::new(RefCell::new(hole.wall_segments()));
 
         let mut seed = Snapshot::new(Vec::new(), Rc::clone(&walls));
 
@@ -75,10 +150,10 @@ impl Multiplay {
                 shape: PrimitiveShape::Path(Path::new()),
             })
             .add_entity()
-            .with_component(Transform(Isometry2::translation(920., 300.)))
+            .with_component(Transform(Isometry2::translation(hole.cup.x, hole.cup.y)))
             .with_component(PrimitiveComponent {
                 color: [0.3, 1., 0.3, 1.],
-                shape: PrimitiveShape::Ball(100., 100.),
+                shape: PrimitiveShape::Ball(hole.cup_radius as f32, hole.cup_radius as f32),
             })
             .add_entity()
             .with_component(Transform(Isometry2::translation(0., 0.)))
@@ -89,17 +164,19 @@ impl Multiplay {
             })
             .build();
 
-        for i in 0..8 {
-            let f = i as f64;
-            let k = f % 2.;
-            let x = 300. + f % 3. * 60.;
-            let y = 200. + (f / 3.).floor() * 60. + k * 10.;
+        let balls = std::iter::once((hole.tee, hole.ball_radius, 1.))
+            .chain(hole.rack.iter().map(|b| (b.position, b.radius, b.mass)));
+
+        // every ball is someone's, so they are drawn in player colors rather than the rack's
+        for (i, (position, radius, mass)) in balls.enumerate() {
+            let (x, y) = (position.x, position.y);
 
             seed.balls.push(PhysicsBody {
-                body: Ball { radius: 21.335 },
+                body: Ball { radius, mass },
                 position: Point2::new(x, y),
                 velocity: Vector2::new(0., 0.),
                 acceleration: Vector2::new(0., 0.),
+                surface: Surface::default(),
             });
 
             let mut entity = world
@@ -108,11 +185,11 @@ impl Multiplay {
                     color: if i == own_ball_index {
                         [0., 1., 1., 1.]
                     } else if i < own_ball_index {
-                        player_colors[i]
+                        player_colors[i % player_colors.len()]
                     } else {
-                        player_colors[i - 1]
+                        player_colors[(i - 1) % player_colors.len()]
                     },
-                    shape: PrimitiveShape::Ball(21.335, 21.335),
+                    shape: PrimitiveShape::Ball(radius as f32, radius as f32),
                 })
                 .with_component(Transform(Isometry2::translation(x, y)))
                 .with_component(BallComponent {
@@ -127,53 +204,95 @@ impl Multiplay {
             world = entity.build();
         }
 
-        let initial_snapshot = seed.clone();
-
+        let seed = seed.with_fixtures(Rc::new(hole.fixtures()));
         snapshots.push(seed);
-
-        let mut seed_index = 0;
-        while let Some(next) = snapshots[seed_index].next_snapshot() {
-            snapshots.push(next);
-            seed_index += 1;
-        }
+        simulate(&mut snapshots);
 
         let snapshots = Rc::new(RefCell::new(snapshots));
         let game_state = Rc::new(RefCell::new(GameState::Waiting));
-
-        Multiplay {
-            systems: vec![
-                Box::new(PhysicsAnimator::<f64>::new(
-                    Rc::clone(&snapshots),
-                    Rc::clone(&timer),
-                )) as Box<dyn SystemCaller<MyWorld>>,
-                Box::new(LineDrawer::new(Rc::clone(&snapshots))),
+        let connection = Rc::new(RefCell::new(ConnectionState::Connected));
+        let putt_status = Rc::new(RefCell::new(PuttStatus::Idle));
+        let puts = Rc::new(RefCell::new(PuttLog::default()));
+        let clock = Rc::new(RefCell::new(GameClock::new()));
+        let courses = if lobby.host {
+            lobby_courses()
+        } else {
+            Vec::new()
+        };
+        let course_name = lobby.course.clone();
+        let lobby = Rc::new(RefCell::new(lobby));
+
+        let systems = if has_ball {
+            vec![
+                Box::new(
+                    PhysicsAnimator::<f64>::new(
+                        Rc::clone(&snapshots),
+                        Rc::clone(&timer),
+                        Some(PLAY_TIME),
+                    )
+                    .with_clock(Rc::clone(&clock)),
+                ) as Box<dyn SystemCaller<MyWorld>>,
+                Box::new(LineDrawer::new(Rc::clone(&timer), Rc::clone(&snapshots))),
                 Box::new(mela::gfx::primitives::PrimitiveRenderer::new()),
                 Box::new(PollerSystem::new(
-                    "http://minigolf.srvrs.eu".to_owned(),
+                    resources.net.clone(),
                     game_id,
                     Rc::clone(&game_state),
-                    Rc::clone(&client),
-                    uuid.clone(),
+                    Rc::clone(&connection),
                     Rc::clone(&snapshots),
-                    initial_snapshot.clone(),
+                    Rc::clone(&puts),
+                    Rc::clone(&clock),
+                    Rc::clone(&lobby),
                 )),
                 Box::new(MultiplayerInput::new(
                     Rc::clone(&timer),
                     Rc::clone(&snapshots),
-                    initial_snapshot,
-                    Rc::clone(&client),
-                    "http://minigolf.srvrs.eu".to_owned(),
+                    puts,
+                    resources.net.clone(),
                     game_id,
                     uuid.clone(),
+                    Rc::clone(&putt_status),
                 )),
-            ],
+            ]
+        } else {
+            Vec::new()
+        };
+
+        Multiplay {
+            systems,
+            resources,
+            lobby,
+            course: course_name,
+            course_error,
+            courses,
+            host_request: None,
+            host_error: None,
             game_state,
-            client,
+            connection,
+            putt_status,
+            clock,
             uuid,
+            game_id,
             own_ball_index,
             world,
         }
     }
+
+    fn host(&mut self, action: HostAction) {
+        self.host_request = Some(self.resources.net.host(HostCommand {
+            id: self.game_id,
+            player: Player {
+                uuid: self.uuid.to_hyphenated().to_string(),
+            },
+            action,
+        }));
+        self.host_error = None;
+    }
+
+    fn back(&self) -> Wrapper {
+        let round = Round::new(Rc::clone(&self.resources.course));
+        Wrapper::Play(Play::new(Rc::clone(&self.resources), round))
+    }
 }
 
 impl DebugDrawable for Multiplay {}
@@ -196,24 +315,124 @@ impl State for Multiplay {
             system.dispatch(&self.world, delta, io_state, render_ctx, debug_ctx);
         }
 
+        // the new course is played from scratch, with the lobby joined the same way
+        let course = self.lobby.borrow().course.clone();
+        if course != self.course {
+            let lobby = self.lobby.borrow().clone();
+
+            return Wrapper::Multiplay(Multiplay::new(
+                Rc::clone(&self.resources),
+                self.uuid,
+                self.game_id,
+                self.own_ball_index,
+                lobby,
+            ));
+        }
+
+        if let Some(result) = self.host_request.as_ref().and_then(Pending::poll) {
+            self.host_request = None;
+            self.host_error = result.err();
+        }
+
         let ui = &debug_ctx.ui;
 
         use mela::imgui::im_str;
         ui.text(im_str!("FPS: {:.2}", ui.io().framerate));
 
+        if let Some(e) = &self.course_error {
+            ui.text(im_str!("Can't load the lobby's course:\n{}", e));
+
+            if ui.button(im_str!("Back"), [120., 25.]) {
+                return self.back();
+            }
+        }
+
+        let mut action = None;
+
         {
+            let lobby = self.lobby.borrow();
             let game_state = self.game_state.borrow();
 
+            if let Some(code) = &lobby.code {
+                ui.text(im_str!("Lobby code: {}", code));
+            }
+
+            let starting = matches!(*game_state, GameState::Waiting | GameState::Warmup);
+
+            // an empty list means no state has arrived yet
+            if !lobby.players.is_empty() && !lobby.players.contains(&self.own_ball_index) {
+                ui.text(im_str!("The host removed you from the lobby"));
+
+                if ui.button(im_str!("Back"), [120., 25.]) {
+                    return self.back();
+                }
+            } else if lobby.host && starting {
+                ui.text(im_str!("{} players are in", lobby.players.len()));
+
+                if ui.button(im_str!("Start now"), [120., 25.]) {
+                    action = Some(HostAction::Start);
+                }
+
+                for &id in lobby
+                    .players
+                    .iter()
+                    .filter(|&&id| id != self.own_ball_index)
+                {
+                    if ui.button(&im_str!("Kick player {}", id), [120., 25.]) {
+                        action = Some(HostAction::Kick(id));
+                    }
+                }
+
+                ui.text(im_str!(
+                    "Course: {}",
+                    lobby.course.as_deref().unwrap_or("arena")
+                ));
+
+                for (name, balls) in &self.courses {
+                    if ui.button(&im_str!("{} ({} balls)", name, balls), [200., 25.]) {
+                        action = Some(HostAction::Course {
+                            name: name.clone(),
+                            balls: *balls,
+                        });
+                    }
+                }
+
+                if let Some(e) = &self.host_error {
+                    ui.text(im_str!("The server said no:\n{}", e));
+                }
+            }
+
             match *game_state {
+                GameState::Waiting if lobby.host => {
+                    ui.text(im_str!("Start the game once everyone is in"))
+                }
+                GameState::Waiting if lobby.code.is_some() => {
+                    ui.text(im_str!("Waiting for the host to start..."))
+                }
                 GameState::Waiting => ui.text(im_str!("Waiting for another player to join...")),
                 GameState::Warmup => ui.text(im_str!("Waiting for additional players...")),
                 GameState::Play => {
                     ui.text(im_str!("Play!"));
-                    ui.text(im_str!("Try to get your ball to end\nup in the green circle when\ntime runs out.\nYou have 4 shots."));
+                    ui.text(im_str!("Try to get your ball to end\nup in the green circle when\ntime runs out.\nYou have {} shots.", PUTTS_PER_PLAYER));
+
+                    if let Some(rtt) = self.clock.borrow().round_trip() {
+                        ui.text(im_str!("Ping: {} ms", rtt.as_millis()));
+                    }
+
+                    match &*self.putt_status.borrow() {
+                        PuttStatus::Idle => (),
+                        PuttStatus::Sending => ui.text(im_str!("Sending putt...")),
+                        PuttStatus::Failed(e) => ui.text(im_str!("Putt taken back: {}", e)),
+                        PuttStatus::RollbackFailed { putt, rollback } => ui.text(im_str!(
+                            "Putt taken back: {}\nThe game may be off until the next update: {}",
+                            putt,
+                            rollback
+                        )),
+                    }
                 }
                 GameState::GameOver(elapsed) => {
                     if elapsed >= Duration::new(25, 0) {
-                        return Wrapper::Play(Play::new());
+                        return self.back();
                     }
 
                     ui.text(im_str!("Game Over!"))
@@ -221,6 +440,24 @@ impl State for Multiplay {
             }
         }
 
+        if let Some(action) = action {
+            self.host(action);
+        }
+
+        match &*self.connection.borrow() {
+            ConnectionState::Connected => (),
+            ConnectionState::Reconnecting(attempt) => {
+                ui.text(im_str!("Reconnecting... (attempt {})", attempt))
+            }
+            ConnectionState::Lost(e) => {
+                ui.text(im_str!("Lost connection to the lobby:\n{}", e));
+
+                if ui.button(im_str!("Back"), [120., 25.]) {
+                    return self.back();
+                }
+            }
+        }
+
         Wrapper::Multiplay(self)
     }
 
@@ -232,38 +469,86 @@ impl State for Multiplay {
 }
 
 struct PollerSystem {
-    client: Rc<Client>,
+    net: NetWorker,
     game_id: usize,
-    uuid: Uuid,
-    endpoint: String,
     snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
     game_state: Rc<RefCell<GameState>>,
+    connection: Rc<RefCell<ConnectionState>>,
+    backoff: Backoff,
     update_timer: Duration,
-    initial_snapshot: Snapshot<f64>,
-    last_put_count: usize,
+    /// Update request in flight, a new one isn't sent before this one is answered
+    update: Option<Pending<PublicStates>>,
+    /// States pushed by the server, updates are only polled for once this has failed
+    push: Option<Subscription>,
+    puts: Rc<RefCell<PuttLog>>,
+    clock: Rc<RefCell<GameClock>>,
+    lobby: Rc<RefCell<LobbyInfo>>,
 }
 
 impl PollerSystem {
     pub fn new(
-        endpoint: String,
+        net: NetWorker,
         game_id: usize,
         game_state: Rc<RefCell<GameState>>,
-        client: Rc<Client>,
-        uuid: Uuid,
+        connection: Rc<RefCell<ConnectionState>>,
         snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
-        initial_snapshot: Snapshot<f64>,
+        puts: Rc<RefCell<PuttLog>>,
+        clock: Rc<RefCell<GameClock>>,
+        lobby: Rc<RefCell<LobbyInfo>>,
     ) -> PollerSystem {
         PollerSystem {
+            push: Some(net.subscribe(game_id)),
+            net,
             game_id,
-            endpoint,
             game_state,
-            client,
-            uuid,
+            connection,
+            backoff: Backoff::default(),
             snapshots,
             update_timer: Duration::new(0, 0),
-            initial_snapshot,
-            last_put_count: 0,
+            update: None,
+            puts,
+            clock,
+            lobby,
+        }
+    }
+
+    /// Updates the connection state and the clock with the outcome of an update
+    fn handle(
+        &mut self,
+        result: Result<PublicStates, ApiError>,
+        received: Instant,
+        round_trip: Option<RoundTrip>,
+    ) {
+        if let Ok(PublicStates::Play(state)) = &result {
+            self.clock
+                .borrow_mut()
+                .sample(state.time, received, round_trip);
         }
+
+        *self.connection.borrow_mut() = match result.and_then(|state| self.apply(state)) {
+            Ok(()) => self.backoff.succeeded(),
+            Err(e) => self.backoff.failed(e),
+        };
+    }
+
+    fn apply(&mut self, state: PublicStates) -> Result<(), ApiError> {
+        self.lobby.borrow_mut().update(&state);
+
+        let state = match state {
+            PublicStates::WaitingForPlayers(_) => GameState::Waiting,
+            PublicStates::Warmup(_) => GameState::Warmup,
+            PublicStates::Play(state) => {
+                self.puts
+                    .borrow_mut()
+                    .splice(&mut self.snapshots.borrow_mut(), state.puts)?;
+
+                GameState::Play
+            }
+            PublicStates::GameOver(s) => GameState::GameOver(s.time),
+        };
+
+        *self.game_state.borrow_mut() = state;
+        Ok(())
     }
 }
 
@@ -282,83 +567,48 @@ impl System<MyWorld> for PollerSystem {
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> () {
-        let poll_rate = Duration::new(1, 0);
+        // while updates are pushed, polls only keep the clock in sync
+        let poll_rate = if self.push.is_none() || self.clock.borrow().round_trip().is_none() {
+            Duration::new(1, 0)
+        } else {
+            Duration::new(5, 0)
+        };
 
         self.update_timer += delta;
 
-        if self.update_timer >= poll_rate {
-            self.update_timer = Duration::new(0, 0);
+        let mut pushed = Vec::new();
 
-            match self
-                .client
-                .get(&self.endpoint)
-                .json(&GetUpdate { id: self.game_id })
-                .send()
-            {
-                Ok(resp) => {
-                    if resp.status() == 200 {
-                        let state = resp.json::<PublicStates>().unwrap();
-                        let mut our_state = self.game_state.borrow_mut();
-
-                        *our_state = match state {
-                            PublicStates::WaitingForPlayers(_) => GameState::Waiting,
-                            PublicStates::Warmup(_) => GameState::Warmup,
-                            PublicStates::Play(mut state) => {
-                                if state.puts.len() != self.last_put_count {
-                                    self.last_put_count = state.puts.len();
-
-                                    let mut snapshots = vec![self.initial_snapshot.clone()];
-
-                                    state.puts.sort_by(|a, b| a.time.cmp(&b.time));
-
-                                    for putt in state.puts {
-                                        let (i, snapshot) = snapshots
-                                            .iter_mut()
-                                            .enumerate()
-                                            .find(|(_, s)| s.end_time > putt.time)
-                                            .unwrap();
-
-                                        snapshot.end_time = putt.time.clone();
-                                        let mut new = snapshot.advance_to(
-                                            (putt.time - snapshot.start_time).as_secs_f64(),
-                                        );
-
-                                        new.ignore_collisions = snapshot.ignore_collisions.clone();
-
-                                        new.balls[putt.player.id].velocity = putt.impulse.into();
-
-                                        let mut new_snapshots: Vec<Snapshot<f64>> =
-                                            snapshots[..=i].iter().cloned().collect();
-                                        new_snapshots.push(new);
-
-                                        let mut seed_index = i + 1;
-
-                                        while let Some(next) =
-                                            new_snapshots[seed_index].next_snapshot()
-                                        {
-                                            new_snapshots.push(next);
-                                            seed_index += 1;
-                                        }
-
-                                        snapshots = new_snapshots;
-                                    }
-
-                                    {
-                                        let mut lock = self.snapshots.borrow_mut();
-                                        *lock = snapshots;
-                                    }
-                                }
-
-                                GameState::Play
-                            }
-                            PublicStates::GameOver(s) => GameState::GameOver(s.time),
-                        }
+        if let Some(push) = &self.push {
+            while let Some(result) = push.poll() {
+                match result {
+                    Ok(state) => pushed.push(state),
+                    // updates are polled for from here on
+                    Err(_) => {
+                        self.push = None;
+                        break;
                     }
                 }
-                Err(e) => {
-                    println!("{}", e);
-                }
             }
         }
+
+        for (state, received) in pushed {
+            self.handle(Ok(state), received, None);
+        }
+
+        if let Some((result, round_trip)) = self.update.as_ref().and_then(Pending::poll_timed) {
+            self.update = None;
+            self.handle(result, round_trip.received, Some(round_trip));
+        }
+
+        let lost = matches!(*self.connection.borrow(), ConnectionState::Lost(_));
+
+        // updates are idempotent, so failed ones are simply tried again a bit later
+        if !lost && self.update.is_none() && self.update_timer >= self.backoff.delay(poll_rate) {
+            self.update_timer = Duration::new(0, 0);
+            self.update = Some(self.net.update(GetUpdate {
+                id: self.game_id,
+                since: self.puts.borrow().cursor(),
+            }));
+        }
     }
 }
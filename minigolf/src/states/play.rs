@@ -1,12 +1,19 @@
 //! play :)
 
-use crate::api::PublicStates;
-use crate::physics::{Ball, BallComponent, PhysicsAnimator, PhysicsBody, Snapshot, Wall};
-use crate::player::{HitIndicator, LineDrawer, PlayerController, PlayerInput, WallComponent};
-use crate::states::multiplay::GameState;
-use crate::states::{walls, Multiplay, Wrapper};
+use crate::course::{daily_course, Course, Hole, RackBall};
+use crate::net::{ApiError, Pending};
+use crate::physics::{
+    remove_last_impulse, Ball, BallComponent, PhysicsAnimator, PhysicsBody, Snapshot, Surface, Wall,
+};
+use crate::player::{
+    zone_color, zone_shape, HitIndicator, LineDrawer, PlayerController, PlayerInput,
+    TrajectoryIndicator, WallComponent,
+};
+use crate::resources::Resources;
+use crate::score::{relative_to_par, score_name, HoleProgress, Round, ScoreKeeper};
+use crate::states::{Editor, LobbyInfo, Multiplay, Scorecard, Wrapper};
 use crate::world::MyWorld;
-use mela::asset::tilemap::{Orthogonal, Tilemap};
+use api::{JoinResponse, PublicStates};
 use mela::debug::{DebugContext, DebugDrawable};
 use mela::ecs::component::Transform;
 use mela::ecs::system::SystemCaller;
@@ -14,25 +21,45 @@ use mela::ecs::world::World;
 use mela::game::IoState;
 use mela::gfx::primitives::{PrimitiveComponent, PrimitiveShape};
 use mela::gfx::RenderContext;
+use mela::imgui::ImString;
 use mela::lyon;
 use mela::lyon::algorithms::path::Path;
 use mela::nphysics::ncollide2d::na::{Isometry2, Point2, Vector2};
 use mela::state::State;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::time::{Duration, Instant};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 pub struct Play {
     world: MyWorld,
     systems: Vec<Box<dyn SystemCaller<MyWorld>>>,
+    resources: Rc<Resources>,
+    round: Round,
+    hole: Rc<Hole>,
+    progress: Rc<RefCell<HoleProgress>>,
+    timer: Rc<RefCell<Duration>>,
+    snapshots: Rc<RefCell<Vec<Snapshot<f64>>>>,
+    /// Set when test playing a hole from the editor
+    editor: Option<Box<Editor>>,
+    /// Lobby join request in flight, with the uuid it was made with
+    joining: Option<(Uuid, Pending<JoinResponse>)>,
+    join_error: Option<ApiError>,
+    /// Join code of a private lobby, as typed in
+    code: ImString,
+    /// Daily challenge being generated in the background, with the day it's for
+    daily: Option<(u64, Receiver<Course>)>,
 }
 
 impl Play {
-    pub fn new() -> Play {
+    pub fn new(resources: Rc<Resources>, round: Round) -> Play {
         let mut timer = Rc::new(RefCell::new(Duration::new(0, 0)));
         let mut snapshots = Vec::new();
-        let walls = walls();
+        let hole = Rc::new(round.current_hole().clone());
+        let walls = Rc::new(RefCell::new(hole.wall_segments()));
+        let progress = Rc::new(RefCell::new(HoleProgress::default()));
 
         let mut seed = Snapshot::new(Vec::new(), Rc::clone(&walls));
 
@@ -42,8 +69,41 @@ impl Play {
             .register::<PrimitiveComponent>()
             .register::<PlayerController>()
             .register::<HitIndicator>()
+            .register::<TrajectoryIndicator>()
             .register::<WallComponent>();
 
+        for zone in &hole.zones {
+            world = world
+                .add_entity()
+                .with_component(Transform(Isometry2::translation(0., 0.)))
+                .with_component(PrimitiveComponent {
+                    color: zone_color(zone),
+                    shape: zone_shape(zone),
+                })
+                .build();
+        }
+
+        // entry pads, and a smaller one where the balls come out
+        for teleporter in &hole.teleporters {
+            let (entry, exit) = (teleporter.entry, teleporter.exit);
+            let radius = teleporter.radius as f32;
+
+            world = world
+                .add_entity()
+                .with_component(Transform(Isometry2::translation(entry.x, entry.y)))
+                .with_component(PrimitiveComponent {
+                    color: [0.6, 0.3, 0.9, 1.],
+                    shape: PrimitiveShape::Ball(radius, radius),
+                })
+                .add_entity()
+                .with_component(Transform(Isometry2::translation(exit.x, exit.y)))
+                .with_component(PrimitiveComponent {
+                    color: [0.6, 0.3, 0.9, 0.5],
+                    shape: PrimitiveShape::Ball(radius / 2., radius / 2.),
+                })
+                .build();
+        }
+
         world = world
             .add_entity()
             .with_component(Transform(Isometry2::translation(0., 0.)))
@@ -53,6 +113,19 @@ impl Play {
                 shape: PrimitiveShape::Path(Path::new()),
             })
             .add_entity()
+            .with_component(Transform(Isometry2::translation(hole.cup.x, hole.cup.y)))
+            .with_component(PrimitiveComponent {
+                color: [0.05, 0.05, 0.05, 1.],
+                shape: PrimitiveShape::Ball(hole.cup_radius as f32, hole.cup_radius as f32),
+            })
+            .add_entity()
+            .with_component(Transform(Isometry2::translation(0., 0.)))
+            .with_component(TrajectoryIndicator {})
+            .with_component(PrimitiveComponent {
+                color: [1., 1., 1., 0.4],
+                shape: PrimitiveShape::Path(Path::new()),
+            })
+            .add_entity()
             .with_component(Transform(Isometry2::translation(0., 0.)))
             .with_component(HitIndicator {})
             .with_component(PrimitiveComponent {
@@ -61,29 +134,32 @@ impl Play {
             })
             .build();
 
-        let radius = 6.1335;
+        let player = RackBall {
+            position: hole.tee,
+            radius: hole.ball_radius,
+            mass: 1.,
+            color: Some([0., 1., 1., 1.]),
+        };
 
-        for i in 0..169 {
-            let f = i as f64;
-            let k = f % 2.;
-            let x = 300. + f % 13. * radius * 3.;
-            let y = 200. + (f / 13.).floor() * radius * 3. + k * radius * 0.3;
+        for (i, ball) in std::iter::once(&player).chain(hole.rack.iter()).enumerate() {
+            let (x, y) = (ball.position.x, ball.position.y);
+            let radius = ball.radius;
 
             seed.balls.push(PhysicsBody {
-                body: Ball { radius },
+                body: Ball {
+                    radius,
+                    mass: ball.mass,
+                },
                 position: Point2::new(x, y),
                 velocity: Vector2::new(0., 0.),
                 acceleration: Vector2::new(0., 0.),
+                surface: Surface::default(),
             });
 
             let mut entity = world
                 .add_entity()
                 .with_component(PrimitiveComponent {
-                    color: if i == 0 {
-                        [0., 1., 1., 1.]
-                    } else {
-                        [1., 0., 1., 1.]
-                    },
+                    color: ball.color.unwrap_or([1., 0., 1., 1.]),
                     shape: PrimitiveShape::Ball(radius as f32, radius as f32),
                 })
                 .with_component(Transform(Isometry2::translation(x, y)))
@@ -99,7 +175,7 @@ impl Play {
             world = entity.build();
         }
 
-        snapshots.push(seed);
+        snapshots.push(seed.with_fixtures(Rc::new(hole.fixtures())));
 
         let mut seed_index = 0;
         while let Some(next) = snapshots[seed_index].next_snapshot() {
@@ -115,13 +191,59 @@ impl Play {
                 Box::new(PhysicsAnimator::<f64>::new(
                     Rc::clone(&snapshots),
                     Rc::clone(&timer),
+                    None,
                 )) as Box<dyn SystemCaller<MyWorld>>,
-                Box::new(PlayerInput::new(Rc::clone(&timer), Rc::clone(&snapshots))),
-                Box::new(LineDrawer::new(Rc::clone(&snapshots))),
+                Box::new(ScoreKeeper::new(
+                    Rc::clone(&snapshots),
+                    Rc::clone(&timer),
+                    Rc::clone(&hole),
+                    Rc::clone(&progress),
+                )),
+                Box::new(PlayerInput::new(
+                    Rc::clone(&timer),
+                    Rc::clone(&snapshots),
+                    Rc::clone(&progress),
+                    Some(3),
+                )),
+                Box::new(LineDrawer::new(Rc::clone(&timer), Rc::clone(&snapshots))),
                 Box::new(mela::gfx::primitives::PrimitiveRenderer::new()),
             ],
+            resources,
+            round,
+            hole,
+            progress,
+            timer,
+            snapshots,
+            editor: None,
+            joining: None,
+            join_error: None,
+            code: ImString::with_capacity(16),
+            daily: None,
         }
     }
+
+    /// Returns to `editor` instead of moving on to the next hole
+    pub fn with_editor(mut self, editor: Option<Box<Editor>>) -> Play {
+        self.editor = editor;
+        self
+    }
+
+    /// Joins a lobby with a new uuid: a random public one, the private one with `code`, or
+    /// a new private one when `private` is set
+    fn join(&mut self, code: Option<String>, private: bool) {
+        let uuid = Uuid::new_v4();
+        let pending = self.resources.net.join(api::JoinGame {
+            id: None,
+            player: api::Player {
+                uuid: uuid.to_hyphenated().to_string(),
+            },
+            code,
+            private,
+        });
+
+        self.joining = Some((uuid, pending));
+        self.join_error = None;
+    }
 }
 
 impl DebugDrawable for Play {}
@@ -149,43 +271,202 @@ impl State for Play {
         use mela::imgui::im_str;
         ui.text(im_str!("FPS: {:.2}", ui.io().framerate));
 
-        if ui.button(im_str!("Join random lobby!"), [150., 30.]) {
-            let uuid = Uuid::new_v4();
-            let client = reqwest::blocking::Client::builder()
-                .connect_timeout(Duration::from_millis(500))
-                .build()
-                .unwrap();
-            let resp = client
-                .post("http://minigolf.srvrs.eu")
-                .json(&crate::api::JoinGame {
-                    id: None,
-                    player: crate::api::Player {
-                        uuid: uuid.to_hyphenated().to_string(),
-                    },
-                })
-                .send();
-
-            match resp {
-                Ok(resp) => {
-                    if resp.status() == 200 {
-                        let resp = resp.json::<crate::api::JoinResponse>().unwrap();
-                        let state = match resp.game {
-                            PublicStates::WaitingForPlayers(s) => GameState::Waiting,
-                            PublicStates::Warmup(s) => GameState::Warmup,
-                            _ => unreachable!(),
-                        };
+        if self.editor.is_some() && ui.button(im_str!("Back to editor"), [120., 25.]) {
+            return Wrapper::Editor(*self.editor.unwrap());
+        }
+
+        {
+            let progress = self.progress.borrow();
+            let par = self.hole.par;
+
+            ui.text(im_str!(
+                "{} ({}/{}) - Par {}",
+                self.hole.name,
+                self.round.hole + 1,
+                self.round.course.holes.len(),
+                par
+            ));
+            ui.text(im_str!("Strokes: {}", progress.strokes));
 
-                        return Wrapper::Multiplay(Multiplay::new(client, uuid, resp.id, resp.uid));
-                    } else {
-                        println!("failed to join game try again later");
+            if let Some(seed) = self.round.seed {
+                ui.text(im_str!("{} (seed {})", self.round.course.name, seed));
+            }
+
+            let mut practice = self.round.practice;
+            if ui.checkbox(im_str!("Practice mode"), &mut practice) {
+                // switching modes starts the hole over. A scored round starts over from the
+                // first hole, so no practice shots or undos end up on its scorecard
+                let round = if practice {
+                    Round {
+                        practice,
+                        ..self.round.clone()
+                    }
+                } else {
+                    Round {
+                        seed: self.round.seed,
+                        ..Round::new(Rc::clone(&self.round.course))
+                    }
+                };
+                let play = Play::new(Rc::clone(&self.resources), round);
+                return Wrapper::Play(play.with_editor(self.editor.take()));
+            }
+
+            if self.round.practice && progress.strokes > 0 {
+                if ui.button(im_str!("Undo shot"), [100., 25.]) {
+                    let mut snapshots = self.snapshots.borrow_mut();
+
+                    if let Some(time) = remove_last_impulse(&mut snapshots) {
+                        *self.timer.borrow_mut() = time;
                     }
                 }
-                Err(e) => {
-                    println!("{}", e);
+            }
+
+            if self.round.strokes.iter().any(Option::is_some) {
+                ui.text(im_str!(
+                    "Round: {}",
+                    relative_to_par(self.round.total_strokes(), self.round.played_par())
+                ));
+            }
+
+            if progress.completed {
+                ui.text(im_str!(
+                    "{} {} ({})",
+                    score_name(progress.strokes, par),
+                    progress.strokes,
+                    relative_to_par(progress.strokes, par)
+                ));
+
+                if ui.button(im_str!("Retry"), [80., 25.]) {
+                    let play = Play::new(Rc::clone(&self.resources), self.round.clone());
+                    return Wrapper::Play(play.with_editor(self.editor.take()));
+                }
+
+                let mut round = self.round.clone();
+                round.record(progress.strokes);
+
+                if self.editor.is_some() {
+                    // test play is a single hole, there's nothing to move on to
+                } else if round.is_last_hole() {
+                    if ui.button(im_str!("Finish round"), [120., 25.]) {
+                        return Wrapper::Scorecard(Scorecard::new(
+                            Rc::clone(&self.resources),
+                            round,
+                        ));
+                    }
+                } else if ui.button(im_str!("Next hole"), [120., 25.]) {
+                    round.hole += 1;
+                    return Wrapper::Play(Play::new(Rc::clone(&self.resources), round));
+                }
+            }
+        }
+
+        if self.editor.is_some() {
+            return Wrapper::Play(self);
+        }
+
+        if self.round.seed.is_none() && ui.button(im_str!("Edit course"), [150., 30.]) {
+            let course = (*self.round.course).clone();
+            return Wrapper::Editor(Editor::new(self.resources, course, self.round.hole));
+        }
+
+        let daily = self
+            .daily
+            .as_ref()
+            .map(|(day, generating)| (*day, generating.try_recv()));
+
+        match daily {
+            Some((day, Ok(course))) => {
+                let mut round = Round::new(Rc::new(course));
+                round.seed = Some(day);
+
+                return Wrapper::Play(Play::new(self.resources, round));
+            }
+            Some((_, Err(TryRecvError::Empty))) => {
+                ui.text(im_str!("Generating the daily challenge..."));
+            }
+            // the generator panicked, let the button try again
+            Some((_, Err(TryRecvError::Disconnected))) => self.daily = None,
+            None => {
+                // generating takes a while with all the validating, so it's not done here
+                if ui.button(im_str!("Daily challenge"), [150., 30.]) {
+                    let day = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs() / (24 * 60 * 60));
+                    let (sender, generating) = channel();
+
+                    thread::spawn(move || {
+                        let _ = sender.send(daily_course(day));
+                    });
+
+                    self.daily = Some((day, generating));
                 }
             }
         }
 
+        let joined = self
+            .joining
+            .as_ref()
+            .and_then(|(uuid, pending)| pending.poll().map(|result| (*uuid, result)));
+
+        match joined {
+            Some((uuid, Ok(resp))) => {
+                self.joining = None;
+
+                match resp.game {
+                    PublicStates::WaitingForPlayers(_) | PublicStates::Warmup(_) => {
+                        let mut lobby = LobbyInfo {
+                            code: resp.code,
+                            host: resp.host,
+                            ..LobbyInfo::default()
+                        };
+                        lobby.update(&resp.game);
+
+                        return Wrapper::Multiplay(Multiplay::new(
+                            Rc::clone(&self.resources),
+                            uuid,
+                            resp.id,
+                            resp.uid,
+                            lobby,
+                        ));
+                    }
+                    _ => {
+                        self.join_error = Some(ApiError::Protocol(format!(
+                            "lobby {} had already started",
+                            resp.id
+                        )))
+                    }
+                }
+            }
+            Some((_, Err(e))) => {
+                self.joining = None;
+                self.join_error = Some(e);
+            }
+            None => (),
+        }
+
+        if self.joining.is_some() {
+            ui.text(im_str!("Joining a lobby..."));
+        } else if !self.resources.settings.offline {
+            if ui.button(im_str!("Join random lobby!"), [150., 30.]) {
+                self.join(None, false);
+            }
+
+            if ui.button(im_str!("Create private game"), [150., 30.]) {
+                self.join(None, true);
+            }
+
+            ui.input_text(im_str!("Code"), &mut self.code).build();
+            let code = self.code.to_str().trim().to_owned();
+
+            if !code.is_empty() && ui.button(im_str!("Join with code"), [150., 30.]) {
+                self.join(Some(code), false);
+            }
+        }
+
+        if let Some(e) = &self.join_error {
+            ui.text(im_str!("Failed to join a lobby:\n{}", e));
+        }
+
         Wrapper::Play(self)
     }
 
@@ -0,0 +1,106 @@
+//! end of round summary
+
+use crate::resources::Resources;
+use crate::score::{relative_to_par, Round};
+use crate::states::{Play, Wrapper};
+use mela::debug::{DebugContext, DebugDrawable};
+use mela::game::IoState;
+use mela::gfx::RenderContext;
+use mela::state::State;
+use std::rc::Rc;
+use std::time::Duration;
+
+pub struct Scorecard {
+    resources: Rc<Resources>,
+    round: Round,
+}
+
+impl Scorecard {
+    pub fn new(resources: Rc<Resources>, round: Round) -> Scorecard {
+        Scorecard { resources, round }
+    }
+}
+
+impl DebugDrawable for Scorecard {}
+
+impl State for Scorecard {
+    type Wrapper = Wrapper;
+
+    fn name(&self) -> &str {
+        "Scorecard"
+    }
+
+    fn update(
+        self,
+        _delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> Self::Wrapper {
+        let ui = &debug_ctx.ui;
+        let round = &self.round;
+
+        use mela::imgui::im_str;
+        ui.text(im_str!("{}", round.course.name));
+
+        if round.practice {
+            ui.text(im_str!("Practice round"));
+        }
+
+        ui.separator();
+
+        for (i, (hole, strokes)) in round.course.holes.iter().zip(&round.strokes).enumerate() {
+            // split into front and back nine like a real scorecard
+            if i == 9 {
+                ui.separator();
+            }
+
+            match strokes {
+                Some(strokes) => ui.text(im_str!(
+                    "{:>2}. {:<16} Par {}  {:>2} ({})",
+                    i + 1,
+                    hole.name,
+                    hole.par,
+                    strokes,
+                    relative_to_par(*strokes, hole.par)
+                )),
+                None => ui.text(im_str!(
+                    "{:>2}. {:<16} Par {}   -",
+                    i + 1,
+                    hole.name,
+                    hole.par
+                )),
+            }
+        }
+
+        ui.separator();
+
+        let total = round.total_strokes();
+        let par = round.played_par();
+
+        ui.text(im_str!("Total: {} (par {})", total, par));
+        ui.text(im_str!("Score: {}", relative_to_par(total, par)));
+
+        if let Some(best) = round.best_hole() {
+            let hole = &round.course.holes[best];
+            let strokes = round.strokes[best].unwrap();
+
+            ui.text(im_str!(
+                "Best hole: {}. {} ({})",
+                best + 1,
+                hole.name,
+                relative_to_par(strokes, hole.par)
+            ));
+        }
+
+        if ui.button(im_str!("Play again"), [120., 30.]) {
+            let mut round = Round::new(Rc::clone(&self.round.course));
+            round.seed = self.round.seed;
+            return Wrapper::Play(Play::new(Rc::clone(&self.resources), round));
+        }
+
+        Wrapper::Scorecard(self)
+    }
+
+    fn redraw(&self, _render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) {}
+}
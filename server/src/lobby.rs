@@ -0,0 +1,823 @@
+//! Lobby state machine: waiting for players, warmup, play and game over
+
+use api::{
+    HostAction, HostCommand, JoinGame, JoinResponse, PublicGameOver, PublicPlay, PublicPlayer,
+    PublicPut, PublicStates, PublicWaitingForPlayers, PublicWarmup, Putt, PuttError, PLAY_TIME,
+    PUTTS_PER_PLAYER, PUTT_TIME_TOLERANCE,
+};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// Players needed before the warmup starts
+pub const MIN_PLAYERS: usize = 2;
+/// One for each ball in the arena
+pub const MAX_PLAYERS: usize = 8;
+/// More players can still join during the warmup
+pub const WARMUP_TIME: Duration = Duration::from_secs(10);
+/// Finished lobbies are kept around this long so every client sees the end of the game
+pub const GAME_OVER_TIME: Duration = Duration::from_secs(30);
+/// Lobbies waiting for players are dropped when nobody has joined or been kicked, and the
+/// host hasn't done anything, for this long
+pub const IDLE_TIME: Duration = Duration::from_secs(10 * 60);
+/// Join codes are made of these, leaving out the ones that are easy to mix up
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 5;
+
+/// Why a request was turned down, sent back to the client as the response body
+#[derive(Debug)]
+pub enum Rejection {
+    BadRequest(String),
+    NoSuchLobby(usize),
+    NoSuchCode(String),
+    Refused(String),
+    /// A putt that broke the rules, sent as JSON so the client can tell why
+    Putt(PuttError),
+}
+
+impl Rejection {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Rejection::BadRequest(_) | Rejection::Putt(PuttError::ImpulseOverLimit) => 400,
+            Rejection::NoSuchLobby(_) | Rejection::NoSuchCode(_) => 404,
+            Rejection::Refused(_) | Rejection::Putt(_) => 409,
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            Rejection::Putt(e) => serde_json::to_string(e).expect("api types always serialize"),
+            rejection => rejection.to_string(),
+        }
+    }
+}
+
+impl From<PuttError> for Rejection {
+    fn from(e: PuttError) -> Rejection {
+        Rejection::Putt(e)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            Rejection::NoSuchLobby(id) => write!(f, "no lobby with id {}", id),
+            Rejection::NoSuchCode(code) => write!(f, "no private lobby with code {}", code),
+            Rejection::Refused(reason) => write!(f, "{}", reason),
+            Rejection::Putt(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Phase {
+    WaitingForPlayers,
+    Warmup(Instant),
+    Play(Instant),
+    GameOver(Instant),
+}
+
+/// Join code and host of a private lobby
+#[derive(Debug)]
+struct Private {
+    code: String,
+    host: usize,
+}
+
+#[derive(Debug)]
+pub struct Lobby {
+    pub id: usize,
+    /// Uuids of the players, the index is both the player's id and their ball on the course.
+    /// Kicked players leave their slot empty, so nobody else's ball changes.
+    players: Vec<Option<String>>,
+    /// Uuids that were kicked out and can't join again
+    kicked: Vec<String>,
+    private: Option<Private>,
+    /// Course file name chosen by the host and how many balls it has, the arena when none
+    course: Option<(String, usize)>,
+    puts: Vec<PublicPut>,
+    phase: Phase,
+    /// Bumped on every change the clients should hear about
+    revision: usize,
+    /// When a player last joined or the host last gave a command, polling doesn't count
+    touched: Instant,
+}
+
+impl Lobby {
+    pub fn new(id: usize, now: Instant) -> Lobby {
+        Lobby {
+            id,
+            players: Vec::new(),
+            kicked: Vec::new(),
+            private: None,
+            course: None,
+            puts: Vec::new(),
+            phase: Phase::WaitingForPlayers,
+            revision: 0,
+            touched: now,
+        }
+    }
+
+    /// A lobby that can only be joined with `code`, hosted by whoever joins first
+    pub fn private(id: usize, code: String, now: Instant) -> Lobby {
+        Lobby {
+            private: Some(Private { code, host: 0 }),
+            ..Lobby::new(id, now)
+        }
+    }
+
+    pub fn code(&self) -> Option<&str> {
+        self.private.as_ref().map(|p| p.code.as_str())
+    }
+
+    /// Moves on to the next phase when it's time. Phases change only when the lobby is
+    /// touched, so each one starts when the previous should have ended rather than at `now`.
+    /// Private lobbies wait for their host to start the game.
+    pub fn update(&mut self, now: Instant) {
+        if let Phase::WaitingForPlayers = self.phase {
+            if self.private.is_none() && self.player_count() >= MIN_PLAYERS {
+                self.phase = Phase::Warmup(now);
+                self.revision += 1;
+            }
+        }
+
+        if let Phase::Warmup(start) = self.phase {
+            if now >= start + WARMUP_TIME {
+                self.phase = Phase::Play(start + WARMUP_TIME);
+                self.revision += 1;
+            }
+        }
+
+        if let Phase::Play(start) = self.phase {
+            if now >= start + PLAY_TIME {
+                self.phase = Phase::GameOver(start + PLAY_TIME);
+                self.revision += 1;
+            }
+        }
+    }
+
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Can new players still join
+    pub fn is_open(&self) -> bool {
+        self.is_starting() && self.players.len() < self.capacity()
+    }
+
+    /// Is the game yet to start
+    fn is_starting(&self) -> bool {
+        matches!(self.phase, Phase::WaitingForPlayers | Phase::Warmup(_))
+    }
+
+    /// Player slots, one for each ball on the course
+    fn capacity(&self) -> usize {
+        self.course
+            .as_ref()
+            .map_or(MAX_PLAYERS, |(_, balls)| MAX_PLAYERS.min(*balls))
+    }
+
+    fn player_count(&self) -> usize {
+        self.players.iter().flatten().count()
+    }
+
+    /// Has the game been over, or the lobby been left waiting, long enough for it to be
+    /// dropped
+    pub fn is_expired(&self, now: Instant) -> bool {
+        match self.phase {
+            Phase::WaitingForPlayers => now >= self.touched + IDLE_TIME,
+            Phase::GameOver(end) => now >= end + GAME_OVER_TIME,
+            _ => false,
+        }
+    }
+
+    fn player(&self, uuid: &str) -> Option<usize> {
+        self.players.iter().position(|p| p.as_deref() == Some(uuid))
+    }
+
+    /// Adds the player to the lobby and returns their id. Joining again with the same uuid
+    /// gives back the same id.
+    pub fn join(&mut self, uuid: &str) -> Result<usize, Rejection> {
+        if let Some(id) = self.player(uuid) {
+            return Ok(id);
+        }
+
+        if self.kicked.iter().any(|k| k == uuid) {
+            return Err(Rejection::Refused(format!(
+                "the host removed you from lobby {}",
+                self.id
+            )));
+        }
+
+        if !self.is_open() {
+            return Err(Rejection::Refused(format!(
+                "lobby {} is full or already playing",
+                self.id
+            )));
+        }
+
+        self.players.push(Some(uuid.to_owned()));
+        self.revision += 1;
+        Ok(self.players.len() - 1)
+    }
+
+    /// Takes the putt if it's within the rules. The ball is the one the uuid joined with, and
+    /// the time has to be close to the server's, so nobody can putt into the past or queue up
+    /// putts for later.
+    pub fn putt(&mut self, putt: &Putt, now: Instant) -> Result<(), PuttError> {
+        let elapsed = match self.phase {
+            Phase::Play(start) => now.saturating_duration_since(start),
+            _ => return Err(PuttError::NotPlaying),
+        };
+
+        let player = self
+            .player(&putt.player.uuid)
+            .ok_or(PuttError::NotAPlayer)?;
+
+        if matches!(putt.ball, Some(ball) if ball != player) {
+            return Err(PuttError::NotYourBall { yours: player });
+        }
+
+        if !putt.impulse_within_limit() {
+            return Err(PuttError::ImpulseOverLimit);
+        }
+
+        if putt.time > PLAY_TIME {
+            return Err(PuttError::AfterGame);
+        }
+
+        if putt.time + PUTT_TIME_TOLERANCE < elapsed {
+            return Err(PuttError::TooLate {
+                behind: elapsed - putt.time,
+            });
+        }
+
+        if putt.time > elapsed + PUTT_TIME_TOLERANCE {
+            return Err(PuttError::TooEarly {
+                ahead: putt.time - elapsed,
+            });
+        }
+
+        let taken = self.puts.iter().filter(|p| p.player.id == player).count();
+        if taken >= PUTTS_PER_PLAYER {
+            return Err(PuttError::NoPuttsLeft);
+        }
+
+        self.puts.push(PublicPut {
+            id: self.puts.len(),
+            player: PublicPlayer { id: player },
+            time: putt.time,
+            impulse: putt.impulse,
+        });
+        self.revision += 1;
+
+        Ok(())
+    }
+
+    /// Carries out a command from the host of a private lobby
+    pub fn host(&mut self, command: &HostCommand, now: Instant) -> Result<(), Rejection> {
+        let host = match &self.private {
+            Some(private) => private.host,
+            None => {
+                return Err(Rejection::Refused(format!(
+                    "lobby {} is public, it has no host",
+                    self.id
+                )))
+            }
+        };
+
+        if self.player(&command.player.uuid) != Some(host) {
+            return Err(Rejection::Refused("only the host can do that".to_owned()));
+        }
+
+        if !self.is_starting() {
+            return Err(Rejection::Refused(
+                "the game has already started".to_owned(),
+            ));
+        }
+
+        match &command.action {
+            HostAction::Start => {
+                if self.player_count() < MIN_PLAYERS {
+                    return Err(Rejection::Refused(format!(
+                        "at least {} players are needed",
+                        MIN_PLAYERS
+                    )));
+                }
+
+                self.phase = Phase::Play(now);
+            }
+            HostAction::Kick(id) => {
+                if *id == host {
+                    return Err(Rejection::BadRequest("the host can't be kicked".to_owned()));
+                }
+
+                let uuid = self
+                    .players
+                    .get_mut(*id)
+                    .and_then(Option::take)
+                    .ok_or_else(|| Rejection::BadRequest(format!("no player {}", id)))?;
+
+                self.kicked.push(uuid);
+            }
+            HostAction::Course { name, balls } => {
+                if !api::is_course_file_name(name) {
+                    return Err(Rejection::BadRequest(format!(
+                        "{:?} is not a course file name",
+                        name
+                    )));
+                }
+
+                if *balls < self.players.len() {
+                    return Err(Rejection::Refused(format!(
+                        "{} only has {} balls, the lobby has {} players",
+                        name,
+                        balls,
+                        self.players.len()
+                    )));
+                }
+
+                self.course = Some((name.clone(), *balls));
+            }
+        }
+
+        self.revision += 1;
+        self.touched = now;
+        Ok(())
+    }
+
+    pub fn put_count(&self) -> usize {
+        self.puts.len()
+    }
+
+    /// The lobby as the clients see it, with the putts from `since` on
+    pub fn public(&self, now: Instant, since: usize) -> PublicStates {
+        let players: Vec<_> = self
+            .players
+            .iter()
+            .enumerate()
+            .filter(|(_, uuid)| uuid.is_some())
+            .map(|(id, _)| PublicPlayer { id })
+            .collect();
+        let course = self.course.as_ref().map(|(name, _)| name.clone());
+
+        match self.phase {
+            Phase::WaitingForPlayers => {
+                PublicStates::WaitingForPlayers(PublicWaitingForPlayers { players, course })
+            }
+            Phase::Warmup(start) => PublicStates::Warmup(PublicWarmup {
+                time: now.saturating_duration_since(start),
+                players,
+                course,
+            }),
+            Phase::Play(start) => PublicStates::Play(PublicPlay {
+                time: now.saturating_duration_since(start),
+                players,
+                puts: self.puts.iter().skip(since).cloned().collect(),
+                course,
+            }),
+            // the server doesn't run the physics, so it can't tell whose ball ended up in the
+            // cup. The clients replay the putts and work out the result themselves.
+            Phase::GameOver(end) => PublicStates::GameOver(PublicGameOver {
+                time: now.saturating_duration_since(end),
+                reserved: players,
+                discard: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// Every lobby on the server. Lobby ids are never reused.
+#[derive(Debug, Default)]
+pub struct Lobbies {
+    lobbies: Vec<Lobby>,
+    last_id: usize,
+}
+
+impl Lobbies {
+    /// Advances every lobby and drops the expired ones
+    pub fn update(&mut self, now: Instant) {
+        for lobby in &mut self.lobbies {
+            lobby.update(now);
+        }
+
+        self.lobbies.retain(|l| !l.is_expired(now));
+    }
+
+    pub fn get(&self, id: usize) -> Result<&Lobby, Rejection> {
+        self.lobbies
+            .iter()
+            .find(|l| l.id == id)
+            .ok_or(Rejection::NoSuchLobby(id))
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Result<&mut Lobby, Rejection> {
+        self.lobbies
+            .iter_mut()
+            .find(|l| l.id == id)
+            .ok_or(Rejection::NoSuchLobby(id))
+    }
+
+    /// Joins the requested lobby, or the first open public one if no id or code was given.
+    /// A new lobby is opened when all the others are full or playing, or a private one was
+    /// asked for.
+    pub fn join(&mut self, join: &JoinGame, now: Instant) -> Result<JoinResponse, Rejection> {
+        let index = if join.private {
+            let code = self.new_code();
+            self.last_id += 1;
+            self.lobbies.push(Lobby::private(self.last_id, code, now));
+            self.lobbies.len() - 1
+        } else if let Some(code) = &join.code {
+            let code = code.trim().to_ascii_uppercase();
+
+            self.lobbies
+                .iter()
+                .position(|l| l.code() == Some(code.as_str()))
+                .ok_or(Rejection::NoSuchCode(code))?
+        } else {
+            match join.id {
+                Some(id) => {
+                    let index = self
+                        .lobbies
+                        .iter()
+                        .position(|l| l.id == id)
+                        .ok_or(Rejection::NoSuchLobby(id))?;
+
+                    if self.lobbies[index].private.is_some() {
+                        return Err(Rejection::Refused(format!(
+                            "lobby {} is private, join it with its code",
+                            id
+                        )));
+                    }
+
+                    index
+                }
+                None => match self
+                    .lobbies
+                    .iter()
+                    .position(|l| l.private.is_none() && l.is_open())
+                {
+                    Some(index) => index,
+                    None => {
+                        self.last_id += 1;
+                        self.lobbies.push(Lobby::new(self.last_id, now));
+                        self.lobbies.len() - 1
+                    }
+                },
+            }
+        };
+
+        let lobby = &mut self.lobbies[index];
+        let uid = lobby.join(&join.player.uuid)?;
+        lobby.touched = now;
+        lobby.update(now);
+
+        Ok(JoinResponse {
+            id: lobby.id,
+            uid,
+            game: lobby.public(now, 0),
+            code: lobby.code().map(str::to_owned),
+            host: matches!(&lobby.private, Some(p) if p.host == uid),
+        })
+    }
+
+    /// A join code no lobby has
+    fn new_code(&self) -> String {
+        loop {
+            // a fresh RandomState is randomly keyed, which is all the randomness needed here
+            let mut bits = RandomState::new().build_hasher().finish();
+
+            let code: String = (0..CODE_LENGTH)
+                .map(|_| {
+                    let c = CODE_CHARS[(bits % CODE_CHARS.len() as u64) as usize];
+                    bits /= CODE_CHARS.len() as u64;
+                    c as char
+                })
+                .collect();
+
+            if self.lobbies.iter().all(|l| l.code() != Some(code.as_str())) {
+                return code;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{Player, MAX_PUTT_IMPULSE};
+
+    /// A public lobby with players "a" and "b", and when its game started
+    fn playing(now: Instant) -> (Lobby, Instant) {
+        let mut lobby = Lobby::new(1, now);
+        lobby.join("a").unwrap();
+        lobby.join("b").unwrap();
+        lobby.update(now);
+        lobby.update(now + WARMUP_TIME);
+
+        (lobby, now + WARMUP_TIME)
+    }
+
+    fn putt(uuid: &str, ball: usize, time: Duration) -> Putt {
+        Putt {
+            id: 1,
+            player: Player {
+                uuid: uuid.to_owned(),
+            },
+            ball: Some(ball),
+            time,
+            impulse: [100., 0.],
+        }
+    }
+
+    #[test]
+    fn putt_accepted() {
+        let (mut lobby, start) = playing(Instant::now());
+        let second = Duration::from_secs(1);
+
+        assert_eq!(lobby.putt(&putt("a", 0, second), start + second), Ok(()));
+        assert_eq!(lobby.put_count(), 1);
+    }
+
+    #[test]
+    fn putt_not_playing() {
+        let now = Instant::now();
+        let mut lobby = Lobby::new(1, now);
+        lobby.join("a").unwrap();
+
+        assert_eq!(
+            lobby.putt(&putt("a", 0, Duration::from_secs(0)), now),
+            Err(PuttError::NotPlaying)
+        );
+    }
+
+    #[test]
+    fn putt_not_a_player() {
+        let (mut lobby, start) = playing(Instant::now());
+
+        assert_eq!(
+            lobby.putt(&putt("c", 0, Duration::from_secs(0)), start),
+            Err(PuttError::NotAPlayer)
+        );
+    }
+
+    #[test]
+    fn putt_not_your_ball() {
+        let (mut lobby, start) = playing(Instant::now());
+
+        assert_eq!(
+            lobby.putt(&putt("a", 1, Duration::from_secs(0)), start),
+            Err(PuttError::NotYourBall { yours: 0 })
+        );
+    }
+
+    #[test]
+    fn putt_impulse_over_limit() {
+        let (mut lobby, start) = playing(Instant::now());
+
+        for impulse in &[[MAX_PUTT_IMPULSE * 2., 0.], [f64::NAN, 0.]] {
+            let putt = Putt {
+                impulse: *impulse,
+                ..putt("a", 0, Duration::from_secs(0))
+            };
+
+            assert_eq!(lobby.putt(&putt, start), Err(PuttError::ImpulseOverLimit));
+        }
+    }
+
+    #[test]
+    fn putt_no_putts_left() {
+        let (mut lobby, start) = playing(Instant::now());
+        let time = Duration::from_secs(0);
+
+        for _ in 0..PUTTS_PER_PLAYER {
+            assert_eq!(lobby.putt(&putt("a", 0, time), start), Ok(()));
+        }
+
+        assert_eq!(
+            lobby.putt(&putt("a", 0, time), start),
+            Err(PuttError::NoPuttsLeft)
+        );
+        assert_eq!(lobby.putt(&putt("b", 1, time), start), Ok(()));
+    }
+
+    #[test]
+    fn putt_after_game() {
+        let (mut lobby, start) = playing(Instant::now());
+        let end = start + PLAY_TIME;
+
+        assert_eq!(
+            lobby.putt(&putt("a", 0, PLAY_TIME + Duration::from_millis(1)), end),
+            Err(PuttError::AfterGame)
+        );
+    }
+
+    #[test]
+    fn putt_too_late() {
+        let (mut lobby, start) = playing(Instant::now());
+        let now = start + Duration::from_secs(5);
+
+        assert_eq!(
+            lobby.putt(&putt("a", 0, Duration::from_secs(1)), now),
+            Err(PuttError::TooLate {
+                behind: Duration::from_secs(4)
+            })
+        );
+    }
+
+    #[test]
+    fn putt_too_early() {
+        let (mut lobby, start) = playing(Instant::now());
+
+        assert_eq!(
+            lobby.putt(&putt("a", 0, Duration::from_secs(2)), start),
+            Err(PuttError::TooEarly {
+                ahead: Duration::from_secs(2)
+            })
+        );
+    }
+
+    fn join(uuid: &str, code: Option<&str>, private: bool) -> JoinGame {
+        JoinGame {
+            id: None,
+            player: Player {
+                uuid: uuid.to_owned(),
+            },
+            code: code.map(str::to_owned),
+            private,
+        }
+    }
+
+    fn command(uuid: &str, action: HostAction) -> HostCommand {
+        HostCommand {
+            id: 1,
+            player: Player {
+                uuid: uuid.to_owned(),
+            },
+            action,
+        }
+    }
+
+    #[test]
+    fn join_with_code() {
+        let now = Instant::now();
+        let mut lobbies = Lobbies::default();
+
+        let hosted = lobbies.join(&join("a", None, true), now).unwrap();
+        let code = hosted.code.unwrap();
+        assert!(hosted.host);
+
+        // codes are matched however they were typed in
+        let typed = format!(" {} ", code.to_ascii_lowercase());
+        let joined = lobbies.join(&join("b", Some(&typed), false), now).unwrap();
+        assert_eq!(joined.id, hosted.id);
+        assert_eq!(joined.uid, 1);
+        assert!(!joined.host);
+
+        assert!(matches!(
+            lobbies.join(&join("c", Some("NOPE1"), false), now),
+            Err(Rejection::NoSuchCode(_))
+        ));
+
+        // private lobbies can't be joined by id, or at random
+        let by_id = JoinGame {
+            id: Some(hosted.id),
+            ..join("c", None, false)
+        };
+        assert!(matches!(
+            lobbies.join(&by_id, now),
+            Err(Rejection::Refused(_))
+        ));
+        assert_ne!(
+            lobbies.join(&join("c", None, false), now).unwrap().id,
+            hosted.id
+        );
+    }
+
+    #[test]
+    fn host_kicks() {
+        let now = Instant::now();
+        let mut lobby = Lobby::private(1, "ABCDE".to_owned(), now);
+        lobby.join("a").unwrap();
+        lobby.join("b").unwrap();
+        lobby.join("c").unwrap();
+
+        assert!(matches!(
+            lobby.host(&command("b", HostAction::Kick(2)), now),
+            Err(Rejection::Refused(_))
+        ));
+        assert!(matches!(
+            lobby.host(&command("a", HostAction::Kick(0)), now),
+            Err(Rejection::BadRequest(_))
+        ));
+
+        lobby.host(&command("a", HostAction::Kick(1)), now).unwrap();
+        assert_eq!(lobby.player_count(), 2);
+        assert!(matches!(lobby.join("b"), Err(Rejection::Refused(_))));
+        assert!(matches!(
+            lobby.host(&command("a", HostAction::Kick(1)), now),
+            Err(Rejection::BadRequest(_))
+        ));
+
+        // the others keep their balls
+        assert_eq!(lobby.join("c").unwrap(), 2);
+        assert_eq!(lobby.join("d").unwrap(), 3);
+    }
+
+    #[test]
+    fn host_picks_course_by_file_name() {
+        let now = Instant::now();
+        let mut lobby = Lobby::private(1, "ABCDE".to_owned(), now);
+        lobby.join("a").unwrap();
+
+        let course = |name: &str| {
+            command(
+                "a",
+                HostAction::Course {
+                    name: name.to_owned(),
+                    balls: 8,
+                },
+            )
+        };
+
+        for name in &[
+            "../settings.json",
+            "/etc/passwd",
+            "C:\\x.json",
+            ".hidden",
+            "",
+        ] {
+            assert!(matches!(
+                lobby.host(&course(name), now),
+                Err(Rejection::BadRequest(_))
+            ));
+        }
+
+        lobby.host(&course("windmill.json"), now).unwrap();
+    }
+
+    #[test]
+    fn host_starts() {
+        let now = Instant::now();
+        let mut lobby = Lobby::private(1, "ABCDE".to_owned(), now);
+        lobby.join("a").unwrap();
+
+        assert!(matches!(
+            lobby.host(&command("a", HostAction::Start), now),
+            Err(Rejection::Refused(_))
+        ));
+
+        // private lobbies don't start on their own
+        lobby.join("b").unwrap();
+        lobby.update(now + WARMUP_TIME);
+        assert!(lobby.is_starting());
+
+        assert!(matches!(
+            lobby.host(&command("b", HostAction::Start), now),
+            Err(Rejection::Refused(_))
+        ));
+
+        lobby.host(&command("a", HostAction::Start), now).unwrap();
+        assert!(matches!(lobby.public(now, 0), PublicStates::Play(_)));
+        assert!(matches!(
+            lobby.host(&command("a", HostAction::Start), now),
+            Err(Rejection::Refused(_))
+        ));
+    }
+
+    #[test]
+    fn public_lobby_has_no_host() {
+        let now = Instant::now();
+        let mut lobby = Lobby::new(1, now);
+        lobby.join("a").unwrap();
+        lobby.join("b").unwrap();
+
+        assert!(matches!(
+            lobby.host(&command("a", HostAction::Start), now),
+            Err(Rejection::Refused(_))
+        ));
+    }
+
+    #[test]
+    fn waiting_lobby_expires() {
+        let now = Instant::now();
+        let later = now + IDLE_TIME / 2;
+        let mut lobby = Lobby::private(1, "ABCDE".to_owned(), now);
+        lobby.join("a").unwrap();
+        lobby.join("b").unwrap();
+
+        // refused commands don't keep the lobby around
+        assert!(lobby
+            .host(&command("b", HostAction::Kick(0)), later)
+            .is_err());
+        assert!(lobby.is_expired(now + IDLE_TIME));
+
+        lobby
+            .host(&command("a", HostAction::Kick(1)), later)
+            .unwrap();
+        assert!(!lobby.is_expired(now + IDLE_TIME));
+        assert!(lobby.is_expired(later + IDLE_TIME));
+    }
+}
@@ -0,0 +1,192 @@
+//! Reference lobby server for multiplayer games
+//!
+//! Run with `cargo run -p minigolf-server -- [address]`, it listens on `127.0.0.1:8080` by
+//! default. The server only keeps track of the lobbies and collects the putts, every client
+//! replays them with its own physics.
+
+mod lobby;
+mod push;
+
+use api::{GetUpdate, HostCommand, JoinGame, Putt};
+use lobby::{Lobbies, Rejection};
+use push::Subscriber;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::WebSocket;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+type Socket = Box<dyn ReadWrite + Send>;
+
+fn main() {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+
+    let server = match listen(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("can't listen on {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("listening on http://{}", address);
+
+    let lobbies = Arc::new(Mutex::new(Lobbies::default()));
+    let (subscribers, new_subscribers) = channel();
+
+    {
+        let lobbies = Arc::clone(&lobbies);
+        thread::spawn(move || push::run::<Socket>(lobbies, new_subscribers));
+    }
+
+    for mut request in server.incoming_requests() {
+        if *request.method() == Method::Get && is_websocket(&request) {
+            subscribe(request, &lobbies, &subscribers);
+            continue;
+        }
+
+        // the body is read before taking the lock, so that a slow client doesn't hold up the
+        // other lobbies
+        let result = match request.method() {
+            Method::Post => parse::<JoinGame>(&mut request).and_then(|join| {
+                let (mut lobbies, now) = lock(&lobbies);
+                let response = lobbies.join(&join, now)?;
+                to_json(&response)
+            }),
+            Method::Get => parse::<GetUpdate>(&mut request).and_then(|update| {
+                let (lobbies, now) = lock(&lobbies);
+                let lobby = lobbies.get(update.id)?;
+                to_json(&lobby.public(now, update.since))
+            }),
+            Method::Put => parse::<Putt>(&mut request).and_then(|putt| {
+                let (mut lobbies, now) = lock(&lobbies);
+                let lobby = lobbies.get_mut(putt.id)?;
+                lobby.putt(&putt, now)?;
+                to_json(&lobby.public(now, 0))
+            }),
+            Method::Patch => parse::<HostCommand>(&mut request).and_then(|command| {
+                let (mut lobbies, now) = lock(&lobbies);
+                let lobby = lobbies.get_mut(command.id)?;
+                lobby.host(&command, now)?;
+                to_json(&lobby.public(now, 0))
+            }),
+            method => Err(Rejection::BadRequest(format!(
+                "unsupported method {}",
+                method
+            ))),
+        };
+
+        respond(request, result);
+    }
+}
+
+/// Starts the HTTP server. Accepted connections inherit the write timeout of the listener, so
+/// the threads writing to subscribers that stopped reading give up instead of blocking forever.
+fn listen(address: &str) -> Result<Server, Box<dyn Error + Send + Sync>> {
+    let listener = socket2::Socket::from(TcpListener::bind(address)?);
+    listener.set_write_timeout(Some(push::WRITE_TIMEOUT))?;
+    Server::from_listener(listener.into_tcp_listener(), None)
+}
+
+/// Locks the lobbies and advances them to now
+fn lock(lobbies: &Mutex<Lobbies>) -> (MutexGuard<'_, Lobbies>, Instant) {
+    let now = Instant::now();
+    let mut lobbies = lobbies.lock().unwrap();
+    lobbies.update(now);
+    (lobbies, now)
+}
+
+fn respond(request: Request, result: Result<String, Rejection>) {
+    let response = match result {
+        Ok(body) => Response::from_string(body).with_header(json_header()),
+        Err(rejection) => {
+            let status = rejection.status_code();
+            let response = Response::from_string(rejection.body()).with_status_code(status);
+
+            match rejection {
+                Rejection::Putt(_) => response.with_header(json_header()),
+                _ => response,
+            }
+        }
+    };
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("failed to respond: {}", e);
+    }
+}
+
+fn json_header() -> Header {
+    "Content-Type: application/json".parse().unwrap()
+}
+
+fn header<'r>(request: &'r Request, name: &'static str) -> Option<&'r str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn is_websocket(request: &Request) -> bool {
+    matches!(header(request, "Upgrade"), Some(u) if u.eq_ignore_ascii_case("websocket"))
+}
+
+/// Lobby id from the `lobby` query parameter
+fn lobby_param(url: &str) -> Option<usize> {
+    let query = &url[url.find('?')? + 1..];
+
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("lobby="))?
+        .parse()
+        .ok()
+}
+
+/// Upgrades the request to a WebSocket and hands it over to the push thread
+fn subscribe(request: Request, lobbies: &Mutex<Lobbies>, subscribers: &Sender<Subscriber<Socket>>) {
+    let lobby = match lobby_param(request.url()) {
+        Some(lobby) => lobby,
+        None => {
+            let rejection = Rejection::BadRequest("missing lobby parameter".to_owned());
+            return respond(request, Err(rejection));
+        }
+    };
+
+    if let Err(rejection) = lobbies.lock().unwrap().get(lobby) {
+        return respond(request, Err(rejection));
+    }
+
+    let accept = match header(&request, "Sec-WebSocket-Key") {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => {
+            let rejection = Rejection::BadRequest("missing Sec-WebSocket-Key".to_owned());
+            return respond(request, Err(rejection));
+        }
+    };
+
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept.as_bytes()).unwrap());
+
+    let socket =
+        WebSocket::from_raw_socket(request.upgrade("websocket", response), Role::Server, None);
+    let _ = subscribers.send(Subscriber { lobby, socket });
+}
+
+fn parse<T: DeserializeOwned>(request: &mut Request) -> Result<T, Rejection> {
+    serde_json::from_reader(request.as_reader()).map_err(|e| Rejection::BadRequest(e.to_string()))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Rejection> {
+    Ok(serde_json::to_string(value).expect("api types always serialize"))
+}
@@ -0,0 +1,145 @@
+//! Pushes lobby states to clients over WebSockets as they change
+//!
+//! Clients open a WebSocket to the server's URL with `?lobby=<id>` and get the lobby's
+//! `PublicStates` as a JSON text message right away and again after every change, until the
+//! lobby is dropped.
+
+use crate::lobby::Lobbies;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{Message, WebSocket};
+
+/// How often the lobbies are checked for changes, phase changes can be this late
+const PUSH_INTERVAL: Duration = Duration::from_millis(20);
+/// How long a single write can take before the subscriber is dropped
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Subscriber<S> {
+    pub lobby: usize,
+    pub socket: WebSocket<S>,
+}
+
+/// Writes states to a subscriber's socket on its own thread, so a slow client can't hold up
+/// the others
+struct Writer {
+    lobby: usize,
+    states: SyncSender<String>,
+    /// When the write in progress started, if any
+    writing: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Writer {
+    fn spawn<S>(mut subscriber: Subscriber<S>) -> Writer
+    where
+        S: Read + Write + Send + 'static,
+    {
+        let lobby = subscriber.lobby;
+        let (states, pending) = mpsc::sync_channel::<String>(1);
+        let writing = Arc::new(Mutex::new(None));
+        let started = Arc::clone(&writing);
+
+        thread::spawn(move || {
+            for state in pending {
+                *started.lock().unwrap() = Some(Instant::now());
+                let written = subscriber.socket.write_message(Message::Text(state));
+                *started.lock().unwrap() = None;
+
+                // a failed or timed out write means the client has gone away, it can still poll
+                if written.is_err() {
+                    return;
+                }
+            }
+
+            // the lobby is gone or the subscriber was dropped
+            let _ = subscriber.socket.close(None);
+            let _ = subscriber.socket.write_pending();
+        });
+
+        Writer {
+            lobby,
+            states,
+            writing,
+        }
+    }
+
+    fn timed_out(&self, now: Instant) -> bool {
+        match *self.writing.lock().unwrap() {
+            Some(started) => now.duration_since(started) > WRITE_TIMEOUT,
+            None => false,
+        }
+    }
+}
+
+/// Sends every subscriber the state of their lobby when it changes. Runs until the server
+/// stops taking subscribers.
+pub fn run<S>(lobbies: Arc<Mutex<Lobbies>>, subscribers: Receiver<Subscriber<S>>)
+where
+    S: Read + Write + Send + 'static,
+{
+    // along with the revision of the lobby last sent to them and the putts they have
+    let mut active: Vec<(Writer, Option<usize>, usize)> = Vec::new();
+
+    loop {
+        loop {
+            match subscribers.try_recv() {
+                Ok(subscriber) => active.push((Writer::spawn(subscriber), None, 0)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let now = Instant::now();
+
+        // the states are built under the lock and written after it's released, `None` for
+        // dropped lobbies and no state for unchanged ones
+        let states: Vec<Option<(Option<String>, usize, usize)>> = {
+            let mut lobbies = lobbies.lock().unwrap();
+            lobbies.update(now);
+
+            active
+                .iter()
+                .map(|(writer, sent, since)| {
+                    let lobby = lobbies.get(writer.lobby).ok()?;
+
+                    if *sent == Some(lobby.revision()) {
+                        return Some((None, lobby.revision(), *since));
+                    }
+
+                    let state = serde_json::to_string(&lobby.public(now, *since))
+                        .expect("api types always serialize");
+                    Some((Some(state), lobby.revision(), lobby.put_count()))
+                })
+                .collect()
+        };
+
+        active = active
+            .into_iter()
+            .zip(states)
+            .filter_map(|((writer, sent, since), state)| {
+                // dropping the writer closes the socket once its last write is done
+                let (state, revision, put_count) = state?;
+
+                if writer.timed_out(now) {
+                    return None;
+                }
+
+                let state = match state {
+                    Some(state) => state,
+                    None => return Some((writer, sent, since)),
+                };
+
+                match writer.states.try_send(state) {
+                    Ok(()) => Some((writer, Some(revision), put_count)),
+                    // still writing an older state, try again on the next round
+                    Err(TrySendError::Full(_)) => Some((writer, sent, since)),
+                    Err(TrySendError::Disconnected(_)) => None,
+                }
+            })
+            .collect();
+
+        thread::sleep(PUSH_INTERVAL);
+    }
+}